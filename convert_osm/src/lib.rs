@@ -3,6 +3,7 @@ mod osm_reader;
 mod split_ways;
 mod srtm;
 
+pub use crate::srtm::Elevation;
 use abstutil::Timer;
 use geom::{Distance, FindClosest, PolyLine, Pt2D};
 use kml::ExtraShapes;
@@ -340,8 +341,20 @@ fn use_amenities(map: &mut RawMap, amenities: Vec<(Pt2D, String, String)>, timer
 fn use_elevation(map: &mut RawMap, path: &str, timer: &mut Timer) {
     timer.start("apply elevation data to intersections");
     let elevation = srtm::Elevation::load(path).unwrap();
+    let mut outside = 0;
     for i in map.intersections.values_mut() {
-        i.elevation = elevation.get(i.point.forcibly_to_gps(&map.gps_bounds));
+        let pt = i.point.forcibly_to_gps(&map.gps_bounds);
+        if elevation.contains(pt) {
+            i.elevation = elevation.get(pt);
+        } else {
+            outside += 1;
+        }
+    }
+    if outside > 0 {
+        timer.warn(format!(
+            "{} intersections are outside the elevation data in {}",
+            outside, path
+        ));
     }
     timer.stop("apply elevation data to intersections");
 }
@@ -14,6 +14,8 @@ const GRID_DIM: usize = 1201;
 pub struct Elevation {
     lon_offset: f64,
     lat_offset: f64,
+    // Number of samples along each side of the square tile
+    grid_dim: usize,
     data: Vec<i16>,
 }
 
@@ -22,26 +24,106 @@ impl Elevation {
         println!("Reading elevation data from {}", path);
         let mut f = File::open(path).unwrap();
 
-        let mut e = Elevation {
-            // TODO dont hardcode
-            lon_offset: -122.0,
-            lat_offset: 47.0,
-            data: Vec::with_capacity(GRID_DIM.pow(2)),
-        };
+        let mut data = Vec::with_capacity(GRID_DIM.pow(2));
         // TODO off by one?
         for _ in 0..GRID_DIM.pow(2) {
-            e.data.push(f.read_i16::<BigEndian>().unwrap());
+            data.push(f.read_i16::<BigEndian>().unwrap());
         }
-        Ok(e)
+        // TODO dont hardcode. The offsets are the southwest corner of the tile.
+        Ok(Elevation::from_grid(47.0, -123.0, GRID_DIM, data))
+    }
+
+    // For elevation that doesn't come from a file, like synthetic terrain. The samples are in
+    // meters, row by row, grid_dim of them per row. Like .hgt files, the first row is the
+    // northern edge of the tile, and each row goes west to east. The samples include both edges,
+    // so neighboring tiles share a row or column.
+    pub fn from_grid(
+        lat_offset: f64,
+        lon_offset: f64,
+        grid_dim: usize,
+        data: Vec<i16>,
+    ) -> Elevation {
+        assert_eq!(data.len(), grid_dim.pow(2));
+        Elevation {
+            lon_offset,
+            lat_offset,
+            grid_dim,
+            data,
+        }
+    }
+
+    // Is the point covered by this tile? The tile spans one degree north and east of the
+    // offsets. get() reads out of bounds otherwise.
+    pub fn contains(&self, pt: LonLat) -> bool {
+        let dx = pt.x() - self.lon_offset;
+        let dy = pt.y() - self.lat_offset;
+        // Mirror the indexing in get()
+        0.0 <= dx && dx < 1.0 && 0.0 <= dy && dy < 1.0
     }
 
     pub fn get(&self, pt: LonLat) -> Distance {
         // TODO assert the (lon, lat) match the offsets
         // TODO not tons of confidence in any of this.
         // TODO interpolate from the 4 matching tiles?
-        let x = ((pt.x() - self.lon_offset).abs() * (GRID_DIM as f64)) as usize;
-        let y = ((pt.y() - self.lat_offset).abs() * (GRID_DIM as f64)) as usize;
-        let i = x + (y * GRID_DIM);
+        // Use the nearest sample. Rows start from the north.
+        let last = (self.grid_dim - 1) as f64;
+        let x = ((pt.x() - self.lon_offset) * last).round() as usize;
+        let y = ((self.lat_offset + 1.0 - pt.y()) * last).round() as usize;
+        let i = x + (y * self.grid_dim);
         Distance::meters(f64::from(self.data[i]))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_contains() {
+        let elevation = Elevation::from_grid(47.0, -123.0, 2, vec![0; 4]);
+        assert!(elevation.contains(LonLat::new(-122.5, 47.5)));
+        // Just south and west of the tile
+        assert!(!elevation.contains(LonLat::new(-123.5, 47.5)));
+        assert!(!elevation.contains(LonLat::new(-122.5, 46.5)));
+        // The east and north edges belong to the next tile
+        assert!(!elevation.contains(LonLat::new(-122.0, 47.5)));
+        assert!(!elevation.contains(LonLat::new(-122.5, 48.0)));
+    }
+
+    #[test]
+    fn test_from_grid() {
+        let flat = Elevation::from_grid(47.0, -123.0, 3, vec![100; 9]);
+        for pt in &[LonLat::new(-123.0, 47.0), LonLat::new(-122.1, 47.9)] {
+            assert_eq!(flat.get(*pt), Distance::meters(100.0));
+        }
+
+        // Rows go south from the northern edge, columns east from the longitude offset
+        let elevation = Elevation::from_grid(47.0, -123.0, 2, vec![1, 2, 3, 4]);
+        for (lon, lat, expected) in &[
+            (-122.75, 47.75, 1.0),
+            (-122.25, 47.75, 2.0),
+            (-122.75, 47.25, 3.0),
+            (-122.25, 47.25, 4.0),
+        ] {
+            assert_eq!(
+                elevation.get(LonLat::new(*lon, *lat)),
+                Distance::meters(*expected)
+            );
+        }
+
+        // The samples include both edges, so the corners and center land exactly on one
+        let elevation = Elevation::from_grid(47.0, -123.0, 3, (1..=9).collect());
+        for (lon, lat, expected) in &[
+            (-123.0, 48.0, 1.0),
+            (-122.0, 48.0, 3.0),
+            (-122.5, 47.5, 5.0),
+            (-123.0, 47.0, 7.0),
+            (-122.0, 47.0, 9.0),
+        ] {
+            assert_eq!(
+                elevation.get(LonLat::new(*lon, *lat)),
+                Distance::meters(*expected)
+            );
+        }
+    }
+}
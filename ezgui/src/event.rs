@@ -385,6 +385,7 @@ impl Key {
 pub enum MultiKey {
     Normal(Key),
     LCtrl(Key),
+    LShift(Key),
     Any(Vec<Key>),
 }
 
@@ -393,6 +394,7 @@ impl MultiKey {
         match self {
             MultiKey::Normal(key) => key.describe(),
             MultiKey::LCtrl(key) => format!("Ctrl+{}", key.describe()),
+            MultiKey::LShift(key) => format!("Shift+{}", key.describe()),
             MultiKey::Any(ref keys) => keys
                 .iter()
                 .map(|k| k.describe())
@@ -400,6 +402,17 @@ impl MultiKey {
                 .join(", "),
         }
     }
+
+    // Plain keys match whether or not shift is held, so letters are case-insensitive. A shifted
+    // key is a different hotkey than the plain one, though.
+    pub(crate) fn matches(&self, pressed: Key, lctrl_held: bool, lshift_held: bool) -> bool {
+        match self {
+            MultiKey::Normal(key) => pressed == *key && !lctrl_held,
+            MultiKey::LCtrl(key) => pressed == *key && lctrl_held,
+            MultiKey::LShift(key) => pressed == *key && lshift_held && !lctrl_held,
+            MultiKey::Any(ref keys) => !lctrl_held && keys.contains(&pressed),
+        }
+    }
 }
 
 pub fn hotkey(key: Key) -> Option<MultiKey> {
@@ -410,6 +423,28 @@ pub fn lctrl(key: Key) -> Option<MultiKey> {
     Some(MultiKey::LCtrl(key))
 }
 
+pub fn lshift(key: Key) -> Option<MultiKey> {
+    Some(MultiKey::LShift(key))
+}
+
 pub fn hotkeys(keys: Vec<Key>) -> Option<MultiKey> {
     Some(MultiKey::Any(keys))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shifted_hotkey() {
+        let shift_a = lshift(Key::A).unwrap();
+        let a = hotkey(Key::A).unwrap();
+        assert!(shift_a.matches(Key::A, false, true));
+        assert!(!shift_a.matches(Key::A, false, false));
+        assert!(!shift_a.matches(Key::B, false, true));
+        // Letters are case-insensitive
+        assert!(a.matches(Key::A, false, true));
+        assert!(a.matches(Key::A, false, false));
+        assert_eq!(shift_a.describe(), "Shift+a");
+    }
+}
@@ -12,6 +12,7 @@ pub struct UserInput {
     reserved_keys: HashMap<Key, String>,
 
     lctrl_held: bool,
+    lshift_held: bool,
 }
 
 impl UserInput {
@@ -22,6 +23,7 @@ impl UserInput {
             important_actions: Vec::new(),
             reserved_keys: HashMap::new(),
             lctrl_held: canvas.lctrl_held,
+            lshift_held: canvas.lshift_held,
         }
     }
 
@@ -75,12 +77,7 @@ impl UserInput {
         }
 
         if let Event::KeyPress(pressed) = self.event {
-            let same = match multikey {
-                MultiKey::Normal(key) => pressed == *key && !self.lctrl_held,
-                MultiKey::LCtrl(key) => pressed == *key && self.lctrl_held,
-                MultiKey::Any(ref keys) => !self.lctrl_held && keys.contains(&pressed),
-            };
-            if same {
+            if multikey.matches(pressed, self.lctrl_held, self.lshift_held) {
                 self.consume_event();
                 return true;
             }
@@ -46,7 +46,7 @@ pub use crate::backend::Drawable;
 pub use crate::canvas::{Canvas, HorizontalAlignment, VerticalAlignment};
 pub use crate::color::{Color, FancyColor, LinearGradient};
 pub use crate::drawing::{GfxCtx, Prerender};
-pub use crate::event::{hotkey, hotkeys, lctrl, Event, Key, MultiKey};
+pub use crate::event::{hotkey, hotkeys, lctrl, lshift, Event, Key, MultiKey};
 pub use crate::event_ctx::EventCtx;
 pub use crate::geom::{GeomBatch, RewriteColor};
 pub use crate::input::UserInput;
@@ -78,6 +78,8 @@ pub(crate) enum InputResult<T: Clone> {
     Canceled,
     StillActive,
     Done(String, T),
+    // Multi-select menus finish with every selected choice
+    DoneMulti(Vec<(String, T)>),
 }
 
 mod backend {
@@ -771,6 +771,9 @@ impl Composite {
     pub fn menu<T: 'static + Clone>(&self, name: &str) -> &Menu<T> {
         self.find(name)
     }
+    pub fn menu_mut<T: 'static + Clone>(&mut self, name: &str) -> &mut Menu<T> {
+        self.find_mut(name)
+    }
 
     pub fn is_checked(&self, name: &str) -> bool {
         self.find::<Checkbox>(name).enabled
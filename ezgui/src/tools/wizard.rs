@@ -1,6 +1,6 @@
 use crate::{
-    hotkey, Btn, Color, Composite, EventCtx, GfxCtx, HorizontalAlignment, InputResult, Key, Line,
-    Menu, MultiKey, Outcome, Text, VerticalAlignment, Widget,
+    hotkey, Btn, Color, Composite, EventCtx, GeomBatch, GfxCtx, HorizontalAlignment, InputResult,
+    Key, Line, Menu, MultiKey, Outcome, Text, VerticalAlignment, Widget,
 };
 use abstutil::Cloneable;
 use std::collections::VecDeque;
@@ -319,6 +319,8 @@ impl<'a, 'b> WrappedWizard<'a, 'b> {
                 let downcasted_item: &R = item.as_any().downcast_ref::<R>().unwrap();
                 (Some((choice.to_string(), downcasted_item.clone())), true)
             }
+            // The wizard doesn't make multi-select menus
+            InputResult::DoneMulti(_) => unreachable!(),
         };
         if destroy {
             self.wizard.menu_comp = None;
@@ -423,6 +425,15 @@ pub struct Choice<T> {
     pub(crate) hotkey: Option<MultiKey>,
     pub(crate) active: bool,
     pub(crate) tooltip: Option<String>,
+    // Explains why an inactive choice can't be picked
+    pub(crate) disabled_reason: Option<String>,
+    pub(crate) icon: Option<GeomBatch>,
+    // Only meaningful in multi-select menus
+    pub(crate) selected: bool,
+    // Shown at the top of menus, before all unpinned choices
+    pub(crate) pinned: bool,
+    // Drawn right-aligned at the end of the row, like a count
+    pub(crate) badge: Option<String>,
 }
 
 impl<T> Choice<T> {
@@ -433,6 +444,11 @@ impl<T> Choice<T> {
             hotkey: None,
             active: true,
             tooltip: None,
+            disabled_reason: None,
+            icon: None,
+            selected: false,
+            pinned: false,
+            badge: None,
         }
     }
 
@@ -464,6 +480,37 @@ impl<T> Choice<T> {
         self
     }
 
+    // Shown when hovering on the choice, only if it's inactive.
+    pub fn disabled_reason<I: Into<String>>(mut self, reason: I) -> Choice<T> {
+        self.disabled_reason = Some(reason.into());
+        self
+    }
+
+    // Drawn in a gutter to the left of the label, scaled to fit one line.
+    pub fn icon(mut self, icon: GeomBatch) -> Choice<T> {
+        self.icon = Some(icon);
+        self
+    }
+
+    pub fn selected(mut self, selected: bool) -> Choice<T> {
+        self.selected = selected;
+        self
+    }
+
+    // Keep this at the top of a menu, like for favorite or recent actions. Pinned choices stay in
+    // the order they're passed in.
+    pub fn pinned(mut self) -> Choice<T> {
+        self.pinned = true;
+        self
+    }
+
+    // Short text like "(1,234)" shown at the right edge of the row. Menu::set_badge can update it
+    // later without touching the label.
+    pub fn badge<I: Into<String>>(mut self, badge: I) -> Choice<T> {
+        self.badge = Some(badge.into());
+        self
+    }
+
     pub(crate) fn with_value<X>(&self, data: X) -> Choice<X> {
         Choice {
             label: self.label.clone(),
@@ -471,6 +518,11 @@ impl<T> Choice<T> {
             hotkey: self.hotkey.clone(),
             active: self.active,
             tooltip: self.tooltip.clone(),
+            disabled_reason: self.disabled_reason.clone(),
+            icon: self.icon.clone(),
+            selected: self.selected,
+            pinned: self.pinned,
+            badge: self.badge.clone(),
         }
     }
 }
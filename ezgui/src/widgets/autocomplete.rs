@@ -104,6 +104,7 @@ impl<T: 'static + Clone> WidgetImpl for Autocomplete<T> {
                 InputResult::Canceled => {
                     self.menu.state = InputResult::StillActive;
                 }
+                InputResult::DoneMulti(_) => unreachable!(),
                 InputResult::Done(ref name, _) => {
                     // Mutating choices is fine, because we're supposed to be consumed by the
                     // caller immediately after this.
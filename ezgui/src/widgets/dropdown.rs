@@ -91,6 +91,7 @@ impl<T: 'static + Clone> WidgetImpl for Dropdown<T> {
                 InputResult::Canceled => {
                     self.menu = None;
                 }
+                InputResult::DoneMulti(_) => unreachable!(),
                 InputResult::Done(_, idx) => {
                     self.menu = None;
                     self.current_idx = idx;
@@ -1,12 +1,21 @@
 use crate::{
-    hotkey, text, Choice, EventCtx, GfxCtx, InputResult, Key, Line, ScreenDims, ScreenPt,
-    ScreenRectangle, Text, Widget, WidgetImpl, WidgetOutput,
+    hotkey, text, Choice, EventCtx, GeomBatch, GfxCtx, InputResult, Key, Line, MultiKey,
+    RewriteColor, ScreenDims, ScreenPt, ScreenRectangle, Text, Widget, WidgetImpl, WidgetOutput,
 };
-use geom::Pt2D;
+use geom::{Angle, Pt2D};
 
 pub struct Menu<T: Clone> {
     choices: Vec<Choice<T>>,
     current_idx: usize,
+    // Width reserved to the left of every label for icons. Zero if no choice has one.
+    icon_gutter: f64,
+    // Width reserved to the right of every label for badges, fitting the widest one. Zero if no
+    // choice has one.
+    badge_column: f64,
+    // Clicking, Space, and hotkeys toggle choices instead of finishing. Enter finishes.
+    multi_select: bool,
+    // Did the highlighted choice move during the last event?
+    just_changed: bool,
 
     pub(crate) state: InputResult<T>,
 
@@ -16,47 +25,217 @@ pub struct Menu<T: Clone> {
 
 impl<T: 'static + Clone> Menu<T> {
     pub fn new(ctx: &EventCtx, choices: Vec<Choice<T>>) -> Widget {
-        let mut m = Menu {
+        Widget::new(Box::new(Menu::build(ctx, choices, false)))
+    }
+
+    // Enter finishes the menu. Get everything selected with take_multi_result.
+    pub fn new_multi_select(ctx: &EventCtx, choices: Vec<Choice<T>>) -> Widget {
+        Widget::new(Box::new(Menu::build(ctx, choices, true)))
+    }
+
+    fn build(ctx: &EventCtx, choices: Vec<Choice<T>>, multi_select: bool) -> Menu<T> {
+        let mut m = Menu::without_dims(choices, multi_select);
+        m.recalculate_dims(ctx);
+        m
+    }
+
+    // Everything but the geometry, which needs recalculate_dims
+    fn without_dims(mut choices: Vec<Choice<T>>, multi_select: bool) -> Menu<T> {
+        // Everything else indexes into choices, so just reorder them once up-front. The sort is
+        // stable, so both groups keep their original order.
+        choices.sort_by_key(|c| !c.pinned);
+        Menu {
             choices,
             current_idx: 0,
+            icon_gutter: 0.0,
+            badge_column: 0.0,
+            multi_select,
+            just_changed: false,
 
             state: InputResult::StillActive,
 
             top_left: ScreenPt::new(0.0, 0.0),
             dims: ScreenDims::new(0.0, 0.0),
+        }
+    }
+
+    fn recalculate_dims(&mut self, ctx: &EventCtx) {
+        self.icon_gutter = icon_gutter(&self.choices, ctx.default_line_height());
+        let assets = &ctx.prerender.assets;
+        self.badge_column = badge_column(
+            self.choices
+                .iter()
+                .filter_map(|c| c.badge.as_ref())
+                .map(|b| Text::from(Line(b)).dims(assets).width),
+            ctx.default_line_height() / 2.0,
+        );
+        self.dims = self.calculate_txt().dims(assets);
+        self.dims.width += self.icon_gutter + self.badge_column;
+    }
+
+    // Changes the badge of every choice with this label, or clears it with None. A wider badge
+    // changes the menu's size, so whatever contains it should re-layout.
+    pub fn set_badge(&mut self, ctx: &EventCtx, label: &str, badge: Option<String>) {
+        for choice in &mut self.choices {
+            if choice.label == label {
+                choice.badge = badge.clone();
+            }
+        }
+        self.recalculate_dims(ctx);
+    }
+
+    // The menu changes size, so whatever contains it should re-layout. Pinned choices still go
+    // above the rest.
+    pub fn add_choice(&mut self, ctx: &EventCtx, choice: Choice<T>) {
+        self.insert_choice(choice);
+        self.recalculate_dims(ctx);
+    }
+
+    fn insert_choice(&mut self, choice: Choice<T>) {
+        let idx = if choice.pinned {
+            self.choices.iter().take_while(|c| c.pinned).count()
+        } else {
+            self.choices.len()
         };
-        m.dims = m.calculate_txt().dims(&ctx.prerender.assets);
-        Widget::new(Box::new(m))
+        self.choices.insert(idx, choice);
+        // Keep the same choice highlighted
+        if idx <= self.current_idx && self.choices.len() > 1 {
+            self.current_idx += 1;
+        }
+    }
+
+    // Removes every choice with this label. If the highlighted choice is removed, the
+    // highlight moves to the next remaining one.
+    pub fn remove_choice(&mut self, ctx: &EventCtx, label: &str) {
+        self.delete_choice(label);
+        self.recalculate_dims(ctx);
+    }
+
+    fn delete_choice(&mut self, label: &str) {
+        let removed_before = self.choices[..self.current_idx.min(self.choices.len())]
+            .iter()
+            .filter(|c| c.label == label)
+            .count();
+        self.choices.retain(|c| c.label != label);
+        self.current_idx -= removed_before;
+        if self.current_idx >= self.choices.len() {
+            self.current_idx = self.choices.len().saturating_sub(1);
+        }
     }
 
     pub fn current_choice(&self) -> &T {
         &self.choices[self.current_idx].data
     }
 
+    // Labels don't have to be unique, so this disambiguates which row was chosen. Once the menu
+    // is done, this is the row that fired, however it was picked. Rows are in display order, so
+    // pinned choices come first.
+    pub fn current_index(&self) -> usize {
+        self.current_idx
+    }
+
+    pub fn selected_choices(&self) -> Vec<(String, T)> {
+        self.choices
+            .iter()
+            .filter(|c| c.selected)
+            .map(|c| (c.label.clone(), c.data.clone()))
+            .collect()
+    }
+
+    // Once Enter finishes a multi-select menu, returns everything selected, in display order.
+    // Afterwards, the menu keeps handling events, so it can be used again.
+    pub fn take_multi_result(&mut self) -> Option<Vec<(String, T)>> {
+        match std::mem::replace(&mut self.state, InputResult::StillActive) {
+            InputResult::DoneMulti(selected) => Some(selected),
+            other => {
+                self.state = other;
+                None
+            }
+        }
+    }
+
+    // True right after the mouse or arrow keys highlight a different choice, without picking it.
+    // Useful for previewing the current choice.
+    pub fn just_changed(&self) -> bool {
+        self.just_changed
+    }
+
+    // Why the current choice is inactive, if it is and somebody said why.
+    pub fn disabled_reason(&self) -> Option<&String> {
+        let choice = self.choices.get(self.current_idx)?;
+        if choice.active {
+            None
+        } else {
+            choice.disabled_reason.as_ref()
+        }
+    }
+
+    fn toggle(&mut self, idx: usize) {
+        self.current_idx = idx;
+        self.choices[idx].selected = !self.choices[idx].selected;
+    }
+
+    // Highlight a row without picking it
+    fn hover(&mut self, idx: usize) {
+        if self.current_idx != idx {
+            self.current_idx = idx;
+            self.just_changed = true;
+        }
+    }
+
+    // Clicking an active row or pressing its hotkey
+    fn pick(&mut self, idx: usize) {
+        if self.multi_select {
+            self.toggle(idx);
+        } else {
+            self.current_idx = idx;
+            let choice = &self.choices[idx];
+            self.state = InputResult::Done(choice.label.clone(), choice.data.clone());
+        }
+    }
+
+    // Pressing Enter. Multi-select menus finish with everything selected, but only from an active
+    // row, same as single-select menus.
+    fn confirm(&mut self) {
+        let choice = &self.choices[self.current_idx];
+        if !choice.active {
+            return;
+        }
+        self.state = if self.multi_select {
+            InputResult::DoneMulti(self.selected_choices())
+        } else {
+            InputResult::Done(choice.label.clone(), choice.data.clone())
+        };
+    }
+
     fn calculate_txt(&self) -> Text {
         let mut txt = Text::new();
 
         for (idx, choice) in self.choices.iter().enumerate() {
+            let mut row = Vec::new();
+            if self.multi_select {
+                // TODO Ideally a checkmark, but the fonts don't seem to have it
+                row.push(Line(if choice.selected { "[x] " } else { "[ ] " }));
+            }
             if choice.active {
                 if let Some(ref key) = choice.hotkey {
-                    txt.add_appended(vec![
-                        Line(key.describe()),
-                        Line(format!(" - {}", choice.label)),
-                    ]);
+                    row.push(Line(key.describe()));
+                    row.push(Line(format!(" - {}", choice.label)));
                 } else {
-                    txt.add(Line(&choice.label));
+                    row.push(Line(&choice.label));
                 }
             } else {
                 if let Some(ref key) = choice.hotkey {
-                    txt.add(
+                    row.push(
                         Line(format!("{} - {}", key.describe(), choice.label))
                             .fg(text::INACTIVE_CHOICE_COLOR),
                     );
                 } else {
-                    txt.add(Line(&choice.label).fg(text::INACTIVE_CHOICE_COLOR));
+                    row.push(Line(&choice.label).fg(text::INACTIVE_CHOICE_COLOR));
                 }
             }
-            if choice.tooltip.is_some() {
+            txt.add_appended(row);
+            if choice.tooltip.is_some() || (!choice.active && choice.disabled_reason.is_some()) {
                 // TODO Ideally unicode info symbol, but the fonts don't seem to have it
                 txt.append(Line(" (!)"));
             }
@@ -68,6 +247,67 @@ impl<T: 'static + Clone> Menu<T> {
         }
         txt
     }
+
+    fn render_rows(&self, g: &GfxCtx) -> GeomBatch {
+        let txt = self.calculate_txt().render_g(g);
+        if self.icon_gutter == 0.0 && self.badge_column == 0.0 {
+            return txt;
+        }
+
+        let mut batch = GeomBatch::new();
+        batch.add_translated(txt, self.icon_gutter, 0.0);
+        let line_height = g.default_line_height();
+        for (idx, choice) in self.choices.iter().enumerate() {
+            if let Some(ref icon) = choice.icon {
+                let dims = icon.get_dims();
+                if dims.width == 0.0 || dims.height == 0.0 {
+                    continue;
+                }
+                // Leave a little padding around the icon
+                let scale = 0.8 * self.icon_gutter / dims.width.max(dims.height);
+                batch.add_transformed(
+                    icon.clone(),
+                    Pt2D::new(self.icon_gutter / 2.0, line_height * (idx as f64 + 0.5)),
+                    scale,
+                    Angle::ZERO,
+                    RewriteColor::NoOp,
+                );
+            }
+            if let Some(ref badge) = choice.badge {
+                let mut line = Line(badge);
+                if !choice.active {
+                    line = line.fg(text::INACTIVE_CHOICE_COLOR);
+                }
+                let badge = Text::from(line).render_g(g);
+                let x = badge_left(self.dims.width, badge.get_dims().width);
+                batch.add_translated(badge, x, line_height * (idx as f64));
+            }
+        }
+        batch
+    }
+}
+
+// Icons are scaled to fit a square one line tall. Zero if no choice has an icon, so labels aren't
+// indented for nothing.
+fn icon_gutter<T>(choices: &[Choice<T>], line_height: f64) -> f64 {
+    if choices.iter().any(|c| c.icon.is_some()) {
+        line_height
+    } else {
+        0.0
+    }
+}
+
+// How much width to reserve for badges, given each one's width. Zero if there are none.
+fn badge_column<I: Iterator<Item = f64>>(badge_widths: I, padding: f64) -> f64 {
+    let widest = badge_widths.fold(None, |widest: Option<f64>, w| {
+        Some(widest.map_or(w, |x| x.max(w)))
+    });
+    widest.map_or(0.0, |w| w + padding)
+}
+
+// Where a badge starts, right-aligned against the end of the row
+fn badge_left(row_width: f64, badge_width: f64) -> f64 {
+    row_width - badge_width
 }
 
 impl<T: 'static + Clone> WidgetImpl for Menu<T> {
@@ -80,6 +320,7 @@ impl<T: 'static + Clone> WidgetImpl for Menu<T> {
     }
 
     fn event(&mut self, ctx: &mut EventCtx, _output: &mut WidgetOutput) {
+        self.just_changed = false;
         if self.choices.is_empty() {
             return;
         }
@@ -101,7 +342,7 @@ impl<T: 'static + Clone> WidgetImpl for Menu<T> {
                         y2: top_left.y + ctx.default_line_height(),
                     };
                     if rect.contains(cursor) {
-                        self.current_idx = idx;
+                        self.hover(idx);
                         break;
                     }
                     top_left.y += ctx.default_line_height();
@@ -122,7 +363,7 @@ impl<T: 'static + Clone> WidgetImpl for Menu<T> {
                 };
                 if let Some(pt) = ctx.canvas.get_cursor_in_screen_space() {
                     if rect.contains(pt) && choice.active {
-                        self.state = InputResult::Done(choice.label.clone(), choice.data.clone());
+                        self.pick(self.current_idx);
                         return;
                     }
                     // Unconsume the click, it was in screen space, but not on us.
@@ -135,35 +376,49 @@ impl<T: 'static + Clone> WidgetImpl for Menu<T> {
             }
         }
 
-        // Handle hotkeys
-        for choice in &self.choices {
-            if !choice.active {
-                continue;
-            }
-            if let Some(ref hotkey) = choice.hotkey {
-                if ctx.input.new_was_pressed(hotkey) {
-                    self.state = InputResult::Done(choice.label.clone(), choice.data.clone());
-                    return;
+        // Handle hotkeys. A plain key also matches when shift is held, so check the shifted
+        // hotkeys first; otherwise shift+A would pick whatever has A.
+        let mut pressed = None;
+        for shifted in &[true, false] {
+            for (idx, choice) in self.choices.iter().enumerate() {
+                if !choice.active {
+                    continue;
+                }
+                if let Some(ref hotkey) = choice.hotkey {
+                    if matches!(hotkey, MultiKey::LShift(_)) == *shifted
+                        && ctx.input.new_was_pressed(hotkey)
+                    {
+                        pressed = Some(idx);
+                        break;
+                    }
                 }
             }
+            if pressed.is_some() {
+                break;
+            }
+        }
+        if let Some(idx) = pressed {
+            self.pick(idx);
+            return;
+        }
+
+        if self.multi_select && ctx.input.new_was_pressed(&hotkey(Key::Space).unwrap()) {
+            if self.choices[self.current_idx].active {
+                self.toggle(self.current_idx);
+            }
+            return;
         }
 
         // Handle nav keys
         if ctx.input.new_was_pressed(&hotkey(Key::Enter).unwrap()) {
-            let choice = &self.choices[self.current_idx];
-            if choice.active {
-                self.state = InputResult::Done(choice.label.clone(), choice.data.clone());
-                return;
-            } else {
-                return;
-            }
+            self.confirm();
         } else if ctx.input.new_was_pressed(&hotkey(Key::UpArrow).unwrap()) {
             if self.current_idx > 0 {
-                self.current_idx -= 1;
+                self.hover(self.current_idx - 1);
             }
         } else if ctx.input.new_was_pressed(&hotkey(Key::DownArrow).unwrap()) {
             if self.current_idx < self.choices.len() - 1 {
-                self.current_idx += 1;
+                self.hover(self.current_idx + 1);
             }
         }
     }
@@ -173,13 +428,16 @@ impl<T: 'static + Clone> WidgetImpl for Menu<T> {
             return;
         }
 
-        let draw = g.upload(self.calculate_txt().render_g(g));
+        let draw = g.upload(self.render_rows(g));
         // In between tooltip and normal screenspace
         g.fork(Pt2D::new(0.0, 0.0), self.top_left, 1.0, Some(0.1));
         g.redraw(&draw);
         g.unfork();
 
-        if let Some(ref info) = self.choices[self.current_idx].tooltip {
+        let info = self
+            .disabled_reason()
+            .or_else(|| self.choices[self.current_idx].tooltip.as_ref());
+        if let Some(info) = info {
             // Hold on, are we actually hovering on that entry right now?
             let mut top_left = self.top_left;
             top_left.y += g.default_line_height() * (self.current_idx as f64);
@@ -199,3 +457,233 @@ impl<T: 'static + Clone> WidgetImpl for Menu<T> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_badge_column() {
+        assert_eq!(badge_column(std::iter::empty(), 5.0), 0.0);
+
+        // Room for the widest badge, plus padding from the label
+        let label_width = 100.0;
+        let column = badge_column([10.0, 30.0].iter().cloned(), 5.0);
+        assert_eq!(column, 35.0);
+        let row_width = label_width + column;
+
+        // Both badges end at the right edge, and neither overlaps the label
+        for badge_width in &[10.0, 30.0] {
+            let x = badge_left(row_width, *badge_width);
+            assert_eq!(x + badge_width, row_width);
+            assert!(x >= label_width);
+        }
+    }
+
+    #[test]
+    fn test_icon_gutter() {
+        let plain = vec![Choice::new("a", 0), Choice::new("b", 1)];
+        assert_eq!(icon_gutter(&plain, 30.0), 0.0);
+
+        // One icon is enough to shift every label over
+        let mixed = vec![
+            Choice::new("a", 0),
+            Choice::new("b", 1).icon(GeomBatch::new()),
+        ];
+        assert_eq!(icon_gutter(&mixed, 30.0), 30.0);
+    }
+
+    #[test]
+    fn test_multi_select() {
+        let mut menu = Menu::without_dims(
+            vec![
+                Choice::new("roads", 0),
+                Choice::new("buildings", 1),
+                Choice::new("parking", 2).active(false),
+                Choice::new("trees", 3),
+            ],
+            true,
+        );
+        // Picking toggles instead of finishing
+        menu.pick(0);
+        menu.pick(3);
+        menu.pick(1);
+        menu.pick(1);
+        assert!(matches!(menu.state, InputResult::StillActive));
+
+        // Enter doesn't finish from an inactive row
+        menu.hover(2);
+        menu.confirm();
+        assert!(matches!(menu.state, InputResult::StillActive));
+
+        menu.hover(3);
+        menu.confirm();
+        match menu.state {
+            InputResult::DoneMulti(ref selected) => assert_eq!(
+                selected,
+                &vec![("roads".to_string(), 0), ("trees".to_string(), 3)]
+            ),
+            _ => panic!("Enter should finish the multi-select menu"),
+        }
+    }
+
+    #[test]
+    fn test_duplicate_labels() {
+        let choices = || vec![Choice::new("same", "first"), Choice::new("same", "second")];
+        for idx in 0..2 {
+            let mut menu = Menu::without_dims(choices(), false);
+            menu.pick(idx);
+            assert_eq!(menu.current_index(), idx);
+            match menu.state {
+                InputResult::Done(ref label, data) => {
+                    assert_eq!(label, "same");
+                    assert_eq!(data, ["first", "second"][idx]);
+                }
+                _ => panic!("Picking a choice should finish the menu"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_disabled_reason() {
+        let mut menu = Menu::without_dims(
+            vec![
+                Choice::new("explained", 0)
+                    .active(false)
+                    .disabled_reason("needs a map"),
+                Choice::new("unexplained", 1).active(false),
+                Choice::new("active", 2).disabled_reason("ignored while active"),
+            ],
+            false,
+        );
+        assert_eq!(menu.disabled_reason(), Some(&"needs a map".to_string()));
+        menu.hover(1);
+        assert_eq!(menu.disabled_reason(), None);
+        menu.hover(2);
+        assert_eq!(menu.disabled_reason(), None);
+    }
+
+    #[test]
+    fn test_pinned_order() {
+        let mut menu = Menu::without_dims(
+            vec![
+                Choice::new("a", 0),
+                Choice::new("b", 1),
+                Choice::new("c", 2).pinned(),
+                Choice::new("d", 3),
+                Choice::new("e", 4).pinned(),
+            ],
+            false,
+        );
+        // Both groups keep their original order
+        let labels: Vec<&str> = menu.choices.iter().map(|c| c.label.as_str()).collect();
+        assert_eq!(labels, vec!["c", "e", "a", "b", "d"]);
+
+        // Moving across rows reports every new highlight, in display order
+        for idx in 1..5 {
+            menu.just_changed = false;
+            menu.hover(idx);
+            assert!(menu.just_changed());
+            assert_eq!(menu.current_index(), idx);
+            assert_eq!(*menu.current_choice(), [2, 4, 0, 1, 3][idx]);
+        }
+        menu.just_changed = false;
+        menu.hover(4);
+        assert!(!menu.just_changed());
+    }
+
+    #[test]
+    fn test_remove_choice() {
+        let mut menu = Menu::without_dims(
+            vec![
+                Choice::new("a", 0),
+                Choice::new("b", 1),
+                Choice::new("c", 2),
+                Choice::new("d", 3),
+            ],
+            false,
+        );
+        // Removing an earlier row keeps the same choice highlighted
+        menu.hover(2);
+        menu.delete_choice("a");
+        assert_eq!(menu.current_index(), 1);
+        assert_eq!(*menu.current_choice(), 2);
+
+        // Removing the highlighted row moves to the next one
+        menu.delete_choice("c");
+        assert_eq!(menu.current_index(), 1);
+        assert_eq!(*menu.current_choice(), 3);
+
+        // Removing the highlighted last row stays in range
+        menu.delete_choice("d");
+        assert_eq!(menu.current_index(), 0);
+        assert_eq!(*menu.current_choice(), 1);
+
+        menu.delete_choice("b");
+        assert!(menu.choices.is_empty());
+        assert_eq!(menu.current_index(), 0);
+    }
+
+    #[test]
+    fn test_pin_third_choice() {
+        let mut menu = Menu::without_dims(vec![Choice::new("a", 0), Choice::new("b", 1)], false);
+        menu.insert_choice(Choice::new("c", 2).pinned());
+        let labels: Vec<&str> = menu.choices.iter().map(|c| c.label.as_str()).collect();
+        assert_eq!(labels, vec!["c", "a", "b"]);
+        // The highlight follows the choice it was on
+        assert_eq!(*menu.current_choice(), 0);
+    }
+
+    #[test]
+    fn test_add_choice() {
+        let mut menu = Menu::without_dims(vec![Choice::new("a", 0)], false);
+        assert_eq!(icon_gutter(&menu.choices, 30.0), 0.0);
+
+        // The new choice can be picked, and its icon makes room for itself
+        menu.insert_choice(Choice::new("b", 1).icon(GeomBatch::new()));
+        assert_eq!(icon_gutter(&menu.choices, 30.0), 30.0);
+        menu.pick(1);
+        match menu.state {
+            InputResult::Done(ref label, data) => {
+                assert_eq!(label, "b");
+                assert_eq!(data, 1);
+            }
+            _ => panic!("The added choice should be selectable"),
+        }
+
+        // Without any icons left, the gutter goes away
+        menu.delete_choice("b");
+        assert_eq!(icon_gutter(&menu.choices, 30.0), 0.0);
+    }
+
+    #[test]
+    fn test_take_multi_result() {
+        let mut menu = Menu::without_dims(
+            vec![
+                Choice::new("roads", 0),
+                Choice::new("buildings", 1),
+                Choice::new("trees", 2),
+            ],
+            true,
+        );
+        menu.pick(2);
+        menu.pick(0);
+        // Nothing to take until Enter
+        assert_eq!(menu.take_multi_result(), None);
+
+        menu.confirm();
+        assert_eq!(
+            menu.take_multi_result(),
+            Some(vec![("roads".to_string(), 0), ("trees".to_string(), 2)])
+        );
+        // Only taken once, and the menu is active again
+        assert_eq!(menu.take_multi_result(), None);
+        assert!(matches!(menu.state, InputResult::StillActive));
+
+        // Single-select menus finish the usual way, which this leaves alone
+        let mut menu = Menu::without_dims(vec![Choice::new("roads", 0)], false);
+        menu.confirm();
+        assert_eq!(menu.take_multi_result(), None);
+        assert!(matches!(menu.state, InputResult::Done(_, 0)));
+    }
+}
@@ -114,6 +114,14 @@ pub struct ColorScheme {
     pub good_to_bad_monochrome_green: [Color; 4],
     pub bus_layer: Color,
     pub edits_layer: Color,
+    pub pandemic_sane: Color,
+    pub pandemic_exposed: Color,
+    pub pandemic_infectious: Color,
+    pub pandemic_hospitalized: Color,
+    pub pandemic_convalescent: Color,
+    pub pandemic_recovered: Color,
+    pub pandemic_dead: Color,
+    pub pandemic_quarantined: Color,
 
     // Misc
     pub parking_trip: Color,
@@ -235,6 +243,14 @@ impl ColorScheme {
             ],
             bus_layer: hex("#4CA7E9"),
             edits_layer: hex("#12409D"),
+            pandemic_sane: hex("#4CA7E9"),
+            pandemic_exposed: hex("#F2C94C"),
+            pandemic_infectious: hex("#EB5757"),
+            pandemic_hospitalized: hex("#96322F"),
+            pandemic_convalescent: hex("#F2994A"),
+            pandemic_recovered: hex("#77C063"),
+            pandemic_dead: Color::grey(0.3),
+            pandemic_quarantined: hex("#9B51E0"),
 
             // Misc
             parking_trip: hex("#4E30A6"),
@@ -68,7 +68,7 @@ impl HeatmapOptions {
                 ctx,
                 "Color scheme",
                 self.color_scheme.clone(),
-                vec!["Turbo", "Inferno", "Warm", "Cool", "Oranges", "Spectral"]
+                all_colormaps()
                     .into_iter()
                     .map(|x| Choice::string(x))
                     .collect(),
@@ -101,6 +101,27 @@ impl HeatmapOptions {
     }
 }
 
+// The names of every colormap that colormap_by_name understands, in the order to offer them
+pub fn all_colormaps() -> Vec<&'static str> {
+    vec![
+        "Turbo", "Inferno", "Warm", "Cool", "Oranges", "Spectral", "Greys",
+    ]
+}
+
+pub fn colormap_by_name(name: &str) -> Option<colorous::Gradient> {
+    match name {
+        "Turbo" => Some(colorous::TURBO),
+        "Inferno" => Some(colorous::INFERNO),
+        "Warm" => Some(colorous::WARM),
+        "Cool" => Some(colorous::COOL),
+        "Oranges" => Some(colorous::ORANGES),
+        "Spectral" => Some(colorous::SPECTRAL),
+        // For printing in black and white
+        "Greys" => Some(colorous::GREYS),
+        _ => None,
+    }
+}
+
 // Returns the colors and labels for each bucket of colors
 pub fn make_heatmap(
     batch: &mut GeomBatch,
@@ -110,15 +131,7 @@ pub fn make_heatmap(
 ) -> (Vec<Color>, Vec<String>) {
     // 7 colors, 8 labels
     let num_colors = 7;
-    let gradient = match opts.color_scheme.as_ref() {
-        "Turbo" => colorous::TURBO,
-        "Inferno" => colorous::INFERNO,
-        "Warm" => colorous::WARM,
-        "Cool" => colorous::COOL,
-        "Oranges" => colorous::ORANGES,
-        "Spectral" => colorous::SPECTRAL,
-        _ => unreachable!(),
-    };
+    let gradient = colormap_by_name(&opts.color_scheme).unwrap();
     let colors: Vec<Color> = (0..num_colors)
         .map(|i| {
             let c = gradient.eval_rational(i, num_colors);
@@ -265,3 +278,32 @@ impl<T: Copy> Grid<T> {
         y * self.width + x
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{all_colormaps, colormap_by_name};
+
+    #[test]
+    fn test_colormap_by_name() {
+        // Every choice offered in the panel has to resolve, or picking it would panic
+        for name in all_colormaps() {
+            assert!(colormap_by_name(name).is_some(), "{} doesn't resolve", name);
+        }
+        assert!(colormap_by_name("Rainbow").is_none());
+        assert!(colormap_by_name("").is_none());
+
+        // The black and white option is offered and really is greyscale
+        assert!(all_colormaps().contains(&"Greys"));
+        let greys = colormap_by_name("Greys").unwrap();
+        for i in 0..=10 {
+            let c = greys.eval_rational(i, 10);
+            assert!(
+                c.r == c.g && c.g == c.b,
+                "({}, {}, {}) isn't grey",
+                c.r,
+                c.g,
+                c.b
+            );
+        }
+    }
+}
@@ -3,12 +3,13 @@ use crate::game::{State, Transition};
 use aabb_quadtree::QuadTree;
 use abstutil::prettyprint_usize;
 use ezgui::{
-    hotkey, Btn, Choice, Color, Composite, Drawable, EventCtx, GeomBatch, GfxCtx,
+    hotkey, Btn, Checkbox, Choice, Color, Composite, Drawable, EventCtx, GeomBatch, GfxCtx,
     HorizontalAlignment, Key, Line, Outcome, Text, TextExt, VerticalAlignment, Widget,
 };
-use geom::{Circle, Distance, PolyLine, Polygon, Pt2D, Ring};
+use geom::{Circle, Distance, FindClosest, PolyLine, Polygon, Pt2D, Ring};
 use kml::ExtraShapes;
-use map_model::BuildingID;
+use map_model::{BuildingID, RoadID};
+use std::cmp::Ordering;
 use std::collections::{BTreeMap, HashSet};
 
 pub struct ViewKML {
@@ -20,6 +21,10 @@ pub struct ViewKML {
     quadtree: QuadTree<usize>,
     query: String,
     draw_query: Drawable,
+    filter: String,
+
+    path: String,
+    snap_to_roads: bool,
 }
 
 struct Object {
@@ -30,13 +35,17 @@ struct Object {
 }
 
 const RADIUS: Distance = Distance::const_meters(5.0);
+const MAX_RADIUS: Distance = Distance::const_meters(15.0);
 const THICKNESS: Distance = Distance::const_meters(2.0);
+const SNAP_THRESHOLD: Distance = Distance::const_meters(10.0);
 
 impl ViewKML {
-    pub fn new(ctx: &mut EventCtx, app: &App, path: String) -> Box<dyn State> {
+    pub fn new(ctx: &mut EventCtx, app: &App, path: String, snap_to_roads: bool) -> Box<dyn State> {
         ctx.loading_screen("load kml", |ctx, mut timer| {
             let raw_shapes = if path.ends_with(".kml") {
                 kml::load(&path, &app.primary.map.get_gps_bounds(), &mut timer).unwrap()
+            } else if path.ends_with(".json") {
+                ExtraShapes::read_json(path.clone(), &mut timer)
             } else {
                 abstutil::read_binary::<ExtraShapes>(path.clone(), &mut timer)
             };
@@ -44,9 +53,18 @@ impl ViewKML {
 
             let dataset_name = abstutil::basename(&path);
 
-            let mut batch = GeomBatch::new();
             let mut objects = Vec::new();
             let mut quadtree = QuadTree::default(app.primary.map.get_bounds().as_bbox());
+            let closest = if snap_to_roads {
+                let mut closest: FindClosest<RoadID> =
+                    FindClosest::new(app.primary.map.get_bounds());
+                for r in app.primary.map.all_roads() {
+                    closest.add(r.id, r.center_pts.points());
+                }
+                Some(closest)
+            } else {
+                None
+            };
             timer.start_iter("convert shapes", raw_shapes.shapes.len());
             for shape in raw_shapes.shapes {
                 timer.next();
@@ -58,10 +76,9 @@ impl ViewKML {
                     .into_iter()
                     .map(|gps| Pt2D::forcibly_from_gps(gps, bounds))
                     .collect();
-                let obj = make_object(app, shape.attributes, pts, &dataset_name);
+                let obj = make_object(app, closest.as_ref(), shape.attributes, pts, &dataset_name);
 
                 quadtree.insert_with_box(objects.len(), obj.polygon.get_bounds().as_bbox());
-                batch.push(Color::RED.alpha(0.8), obj.polygon.clone());
                 objects.push(obj);
             }
 
@@ -77,7 +94,7 @@ impl ViewKML {
             }
 
             Box::new(ViewKML {
-                draw: ctx.upload(batch),
+                draw: ctx.upload(draw_objects(&objects, "")),
                 composite: Composite::new(
                     Widget::col(vec![
                         Widget::row(vec![
@@ -100,6 +117,11 @@ impl ViewKML {
                             Widget::dropdown(ctx, "query", "None".to_string(), choices),
                         ]),
                         "Query matches 0 objects".draw_text(ctx).named("matches"),
+                        Widget::row(vec![
+                            "Only show:".draw_text(ctx).margin_right(10),
+                            Widget::text_entry(ctx, String::new(), false).named("filter"),
+                        ]),
+                        Checkbox::text(ctx, "snap lines to roads", None, snap_to_roads),
                     ])
                     .padding(10)
                     .bg(app.cs.panel_bg),
@@ -111,6 +133,9 @@ impl ViewKML {
                 selected: None,
                 query: "None".to_string(),
                 draw_query: ctx.upload(GeomBatch::new()),
+                filter: String::new(),
+                path,
+                snap_to_roads,
             })
         })
     }
@@ -127,7 +152,8 @@ impl State for ViewKML {
                         .get_bounds()
                         .as_bbox(),
                 ) {
-                    if self.objects[*idx].polygon.contains_pt(pt) {
+                    let obj = &self.objects[*idx];
+                    if obj.polygon.contains_pt(pt) && filter_matches(&self.filter, obj) {
                         self.selected = Some(*idx);
                         break;
                     }
@@ -142,12 +168,29 @@ impl State for ViewKML {
                 }
                 _ => unreachable!(),
             },
-            None => {}
+            None => {
+                if self.composite.is_checked("snap lines to roads") != self.snap_to_roads {
+                    return Transition::Replace(ViewKML::new(
+                        ctx,
+                        app,
+                        self.path.clone(),
+                        !self.snap_to_roads,
+                    ));
+                }
+            }
         }
 
+        let filter = self.composite.text_box("filter");
+        let filter_changed = filter != self.filter;
+        if filter_changed {
+            self.draw = ctx.upload(draw_objects(&self.objects, &filter));
+            self.filter = filter;
+        }
+
+        // Hidden objects don't count toward the query either
         let query: String = self.composite.dropdown_value("query");
-        if query != self.query {
-            let (batch, cnt) = make_query(app, &self.objects, &query);
+        if query != self.query || filter_changed {
+            let (batch, cnt) = make_query(app, &self.objects, &query, &self.filter);
             self.draw_query = ctx.upload(batch);
             self.query = query;
             self.composite.replace(
@@ -186,17 +229,22 @@ impl State for ViewKML {
 
 fn make_object(
     app: &App,
+    closest: Option<&FindClosest<RoadID>>,
     attribs: BTreeMap<String, String>,
     pts: Vec<Pt2D>,
     dataset_name: &str,
 ) -> Object {
     let polygon = if pts.len() == 1 {
-        Circle::new(pts[0], RADIUS).to_polygon()
+        Circle::new(pts[0], point_radius(&attribs)).to_polygon()
     } else if pts[0] == *pts.last().unwrap() {
         // TODO Toggle between these better
         //Polygon::new(&pts)
         Ring::new(pts).make_polygons(THICKNESS)
     } else {
+        let pts = match closest {
+            Some(closest) => snap_to_road(closest, pts),
+            None => pts,
+        };
         PolyLine::new(pts).make_polygons(THICKNESS)
     };
 
@@ -219,10 +267,105 @@ fn make_object(
     }
 }
 
-fn make_query(app: &App, objects: &Vec<Object>, query: &str) -> (GeomBatch, usize) {
+fn draw_objects(objects: &Vec<Object>, filter: &str) -> GeomBatch {
+    let mut batch = GeomBatch::new();
+    for obj in objects {
+        if filter_matches(filter, obj) {
+            batch.push(Color::RED.alpha(0.8), obj.polygon.clone());
+        }
+    }
+    batch
+}
+
+// Filters look like "SW_WIDTH >= 60" or "type = sidewalk". When both sides are numbers, they're
+// compared numerically. Objects missing the attribute don't match. Anything that doesn't parse as a
+// filter (including nothing at all) matches everything.
+fn filter_matches(filter: &str, obj: &Object) -> bool {
+    for op in &[">=", "<=", "!=", "=", ">", "<"] {
+        if let Some(idx) = filter.find(op) {
+            let key = filter[..idx].trim();
+            let expected = filter[idx + op.len()..].trim();
+            if key.is_empty() || expected.is_empty() {
+                return true;
+            }
+            let actual = match obj.attribs.get(key) {
+                Some(v) => v,
+                None => {
+                    return false;
+                }
+            };
+            let ord = match (actual.parse::<f64>(), expected.parse::<f64>()) {
+                (Ok(a), Ok(b)) => a.partial_cmp(&b),
+                _ => Some(actual.as_str().cmp(expected)),
+            };
+            return match ord {
+                Some(ord) => match *op {
+                    ">=" => ord != Ordering::Less,
+                    "<=" => ord != Ordering::Greater,
+                    "!=" => ord != Ordering::Equal,
+                    "=" => ord == Ordering::Equal,
+                    ">" => ord == Ordering::Greater,
+                    "<" => ord == Ordering::Less,
+                    _ => unreachable!(),
+                },
+                None => false,
+            };
+        }
+    }
+    true
+}
+
+// Lines annotating roads (like counts or markings) are usually drawn a bit beside the road. If
+// every point is close to the same road, project the line onto that road's center. This is opt-in,
+// since it hides where the data actually put the line.
+fn snap_to_road(closest: &FindClosest<RoadID>, pts: Vec<Pt2D>) -> Vec<Pt2D> {
+    let road = match closest.closest_pt(pts[0], SNAP_THRESHOLD) {
+        Some((r, _)) => r,
+        None => {
+            return pts;
+        }
+    };
+    let mut snapped = Vec::new();
+    for pt in &pts {
+        match closest
+            .all_close_pts(*pt, SNAP_THRESHOLD)
+            .into_iter()
+            .find(|(r, _, _)| *r == road)
+        {
+            Some((_, snapped_pt, _)) => {
+                snapped.push(snapped_pt);
+            }
+            None => {
+                return pts;
+            }
+        }
+    }
+    let snapped = Pt2D::approx_dedupe(snapped, Distance::meters(0.1));
+    if snapped.len() < 2 {
+        return pts;
+    }
+    snapped
+}
+
+// Some datasets (like collisions) count how many people were involved at a point. Scale the area
+// of the circle by that, so magnitude is visible at a glance.
+fn point_radius(attribs: &BTreeMap<String, String>) -> Distance {
+    let count: usize = ["PEDCOUNT", "PEDCYLCOUNT"]
+        .iter()
+        .filter_map(|key| attribs.get(*key))
+        .filter_map(|value| value.parse::<usize>().ok())
+        .sum();
+    if count <= 1 {
+        return RADIUS;
+    }
+    (RADIUS * (count as f64).sqrt()).min(MAX_RADIUS)
+}
+
+fn make_query(app: &App, objects: &Vec<Object>, query: &str, filter: &str) -> (GeomBatch, usize) {
     let mut batch = GeomBatch::new();
     let mut cnt = 0;
     let color = Color::BLUE.alpha(0.8);
+    let objects = objects.iter().filter(|obj| filter_matches(filter, obj));
     match query {
         "None" => {}
         "parcels without buildings" => {
@@ -279,3 +422,114 @@ fn make_query(app: &App, objects: &Vec<Object>, query: &str) -> (GeomBatch, usiz
     }
     (batch, cnt)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        filter_matches, point_radius, snap_to_road, Object, MAX_RADIUS, RADIUS, SNAP_THRESHOLD,
+    };
+    use geom::{Bounds, Circle, Distance, FindClosest, Pt2D};
+    use map_model::RoadID;
+    use std::collections::BTreeMap;
+
+    fn attribs(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    // One road running east along y = 0
+    fn one_road() -> FindClosest<RoadID> {
+        let center = vec![Pt2D::new(0.0, 0.0), Pt2D::new(100.0, 0.0)];
+        let mut bounds = Bounds::from(&center);
+        bounds.update(Pt2D::new(-50.0, -50.0));
+        bounds.update(Pt2D::new(150.0, 50.0));
+        let mut closest = FindClosest::new(&bounds);
+        closest.add(RoadID(0), &center);
+        closest
+    }
+
+    #[test]
+    fn test_snap_to_road() {
+        let closest = one_road();
+        let within = SNAP_THRESHOLD.inner_meters() - 1.0;
+        let beyond = SNAP_THRESHOLD.inner_meters() + 1.0;
+
+        // A line just beside the road lands on its center
+        let snapped = snap_to_road(
+            &closest,
+            vec![Pt2D::new(10.0, within), Pt2D::new(90.0, within)],
+        );
+        assert_eq!(snapped.len(), 2);
+        assert!(snapped[0].approx_eq(Pt2D::new(10.0, 0.0), Distance::meters(0.01)));
+        assert!(snapped[1].approx_eq(Pt2D::new(90.0, 0.0), Distance::meters(0.01)));
+
+        // Too far away, so it's left alone
+        let far = vec![Pt2D::new(10.0, beyond), Pt2D::new(90.0, beyond)];
+        assert_eq!(snap_to_road(&closest, far.clone()), far);
+
+        // Only one end is close enough, so the whole line is left alone
+        let partly = vec![Pt2D::new(10.0, within), Pt2D::new(90.0, beyond)];
+        assert_eq!(snap_to_road(&closest, partly.clone()), partly);
+    }
+
+    #[test]
+    fn test_point_radius() {
+        // No counts, zero, or garbage all get the normal size
+        assert_eq!(point_radius(&attribs(&[])), RADIUS);
+        assert_eq!(point_radius(&attribs(&[("PEDCOUNT", "0")])), RADIUS);
+        assert_eq!(point_radius(&attribs(&[("PEDCOUNT", "lots")])), RADIUS);
+
+        // The area scales with the total count
+        assert_eq!(
+            point_radius(&attribs(&[("PEDCOUNT", "1"), ("PEDCYLCOUNT", "3")])),
+            RADIUS * 2.0
+        );
+
+        // But huge counts don't cover the map
+        assert_eq!(point_radius(&attribs(&[("PEDCOUNT", "1000")])), MAX_RADIUS);
+    }
+
+    #[test]
+    fn test_filter_matches() {
+        let obj = Object {
+            polygon: Circle::new(Pt2D::new(0.0, 0.0), RADIUS).to_polygon(),
+            attribs: attribs(&[("SW_WIDTH", "60"), ("type", "sidewalk")]),
+            osm_bldg: None,
+        };
+        let matches = |filter| filter_matches(filter, &obj);
+
+        // Every operator, with a number on either side of the boundary
+        assert!(matches("SW_WIDTH >= 60"));
+        assert!(!matches("SW_WIDTH >= 61"));
+        assert!(matches("SW_WIDTH <= 60"));
+        assert!(!matches("SW_WIDTH <= 59"));
+        assert!(matches("SW_WIDTH != 59"));
+        assert!(!matches("SW_WIDTH != 60"));
+        assert!(matches("SW_WIDTH = 60"));
+        assert!(!matches("SW_WIDTH = 59"));
+        assert!(matches("SW_WIDTH > 59"));
+        assert!(!matches("SW_WIDTH > 60"));
+        assert!(matches("SW_WIDTH < 61"));
+        assert!(!matches("SW_WIDTH < 60"));
+
+        // Numbers compare numerically, not as strings
+        assert!(!matches("SW_WIDTH > 100.5"));
+        assert!(matches("SW_WIDTH < 100"));
+
+        // Strings compare as strings
+        assert!(matches("type = sidewalk"));
+        assert!(matches("type != curb"));
+
+        // Missing attributes never match
+        assert!(!matches("SURFACE = concrete"));
+        assert!(!matches("SURFACE != concrete"));
+
+        // Anything that isn't a filter matches everything
+        assert!(matches(""));
+        assert!(matches("SW_WIDTH"));
+        assert!(matches("SW_WIDTH >="));
+        assert!(matches(">= 60"));
+    }
+}
@@ -136,10 +136,12 @@ fn choose_kml(wiz: &mut Wizard, ctx: &mut EventCtx, app: &mut App) -> Option<Tra
     let path = wiz.wrap(ctx).choose_string("View what KML dataset?", || {
         abstutil::list_dir(std::path::Path::new("../data/input/seattle/"))
             .into_iter()
-            .filter(|x| x.ends_with(".bin") && !x.ends_with("popdat.bin"))
+            .filter(|x| (x.ends_with(".bin") && !x.ends_with("popdat.bin")) || x.ends_with(".json"))
             .collect()
     })?;
-    Some(Transition::Replace(kml::ViewKML::new(ctx, app, path)))
+    Some(Transition::Replace(kml::ViewKML::new(
+        ctx, app, path, false,
+    )))
 }
 
 fn load_map(wiz: &mut Wizard, ctx: &mut EventCtx, app: &mut App) -> Option<Transition> {
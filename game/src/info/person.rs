@@ -249,29 +249,56 @@ pub fn bio(
     // barefoot marathons)
 
     if let Some(p) = app.primary.sim.get_pandemic_model() {
-        // TODO add hospitalization/quarantine probably
-        let status = if p.is_sane(id) {
-            "Susceptible".to_string()
+        let (status, color) = if p.is_sane(id) {
+            ("Susceptible".to_string(), app.cs.pandemic_sane)
         } else if p.is_exposed(id) {
-            format!("Exposed at {}", p.get_time(id).unwrap().ampm_tostring())
+            (
+                format!("Exposed at {}", p.get_time(id).unwrap().ampm_tostring()),
+                app.cs.pandemic_exposed,
+            )
+        } else if p.is_hospitalized(id) {
+            (
+                format!(
+                    "Hospitalized at {}",
+                    p.get_time(id).unwrap().ampm_tostring()
+                ),
+                app.cs.pandemic_hospitalized,
+            )
         } else if p.is_infectious(id) {
-            format!("Infected at {}", p.get_time(id).unwrap().ampm_tostring())
+            (
+                format!("Infected at {}", p.get_time(id).unwrap().ampm_tostring()),
+                app.cs.pandemic_infectious,
+            )
+        } else if p.is_convalescent(id) {
+            (
+                format!(
+                    "No longer infectious since {}",
+                    p.get_time(id).unwrap().ampm_tostring()
+                ),
+                app.cs.pandemic_convalescent,
+            )
         } else if p.is_recovered(id) {
-            format!("Recovered at {}", p.get_time(id).unwrap().ampm_tostring())
+            (
+                format!("Recovered at {}", p.get_time(id).unwrap().ampm_tostring()),
+                app.cs.pandemic_recovered,
+            )
         } else if p.is_dead(id) {
-            format!("Dead at {}", p.get_time(id).unwrap().ampm_tostring())
+            (
+                format!("Dead at {}", p.get_time(id).unwrap().ampm_tostring()),
+                app.cs.pandemic_dead,
+            )
         } else {
-            // TODO More info here? Make these public too?
-            "Other (hospitalized or quarantined)".to_string()
+            // Still sane, but their exposure is already scheduled
+            ("Exposure pending".to_string(), app.cs.pandemic_sane)
         };
-        rows.push(
-            Text::from_all(vec![
-                Line("Pandemic model state: ").secondary(),
-                Line(status),
-            ])
-            .draw(ctx)
-            .margin_below(5),
-        );
+        let mut txt = Text::from_all(vec![
+            Line("Pandemic model state: ").secondary(),
+            Line(status).fg(color),
+        ]);
+        if p.is_quarantined(id) {
+            txt.append(Line(", quarantined").fg(app.cs.pandemic_quarantined));
+        }
+        rows.push(txt.draw(ctx).margin_below(5));
     }
 
     let mut has_bike = false;
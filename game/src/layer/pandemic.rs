@@ -1,4 +1,5 @@
 use crate::app::App;
+use crate::colors::ColorScheme;
 use crate::common::{make_heatmap, HeatmapOptions};
 use crate::layer::{Layer, LayerOutcome};
 use abstutil::prettyprint_usize;
@@ -70,6 +71,7 @@ impl Pandemic {
             SEIR::Sane => model.is_sane(p),
             SEIR::Exposed => model.is_exposed(p),
             SEIR::Infected => model.is_exposed(p),
+            SEIR::Hospitalized => model.is_hospitalized(p),
             SEIR::Recovered => model.is_recovered(p),
             SEIR::Dead => model.is_dead(p),
         };
@@ -122,8 +124,9 @@ impl Pandemic {
         } else {
             // It's quite silly to produce triangles for the same circle over and over again. ;)
             let circle = Circle::new(Pt2D::new(0.0, 0.0), Distance::meters(10.0)).to_polygon();
+            let color = opts.state.color(&app.cs).alpha(0.8);
             for pt in pts {
-                batch.push(Color::RED.alpha(0.8), circle.translate(pt.x(), pt.y()));
+                batch.push(color, circle.translate(pt.x(), pt.y()));
             }
             None
         };
@@ -155,10 +158,24 @@ pub enum SEIR {
     Sane,
     Exposed,
     Infected,
+    Hospitalized,
     Recovered,
     Dead,
 }
 
+impl SEIR {
+    pub fn color(self, cs: &ColorScheme) -> Color {
+        match self {
+            SEIR::Sane => cs.pandemic_sane,
+            SEIR::Exposed => cs.pandemic_exposed,
+            SEIR::Infected => cs.pandemic_infectious,
+            SEIR::Hospitalized => cs.pandemic_hospitalized,
+            SEIR::Recovered => cs.pandemic_recovered,
+            SEIR::Dead => cs.pandemic_dead,
+        }
+    }
+}
+
 #[derive(Clone, PartialEq)]
 pub struct Options {
     // If None, just a dot map
@@ -224,6 +241,7 @@ fn make_controls(
                 Choice::new("sane", SEIR::Sane),
                 Choice::new("exposed", SEIR::Exposed),
                 Choice::new("infected", SEIR::Infected),
+                Choice::new("hospitalized", SEIR::Hospitalized),
                 Choice::new("recovered", SEIR::Recovered),
                 Choice::new("dead", SEIR::Dead),
             ],
@@ -244,3 +262,36 @@ fn make_controls(
         .aligned(HorizontalAlignment::Right, VerticalAlignment::Center)
         .build(ctx)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::SEIR;
+    use crate::colors::{ColorScheme, ColorSchemeChoice};
+
+    #[test]
+    fn test_distinct_colors() {
+        // Every state, plus the extra ones in the person panel, needs its own color to be told
+        // apart.
+        for choice in ColorSchemeChoice::choices() {
+            let cs = ColorScheme::new(choice.data);
+            let mut colors = vec![cs.pandemic_convalescent, cs.pandemic_quarantined];
+            for state in &[
+                SEIR::Sane,
+                SEIR::Exposed,
+                SEIR::Infected,
+                SEIR::Hospitalized,
+                SEIR::Recovered,
+                SEIR::Dead,
+            ] {
+                let color = state.color(&cs);
+                assert!(
+                    !colors.contains(&color),
+                    "{} reuses {}",
+                    choice.label,
+                    color
+                );
+                colors.push(color);
+            }
+        }
+    }
+}
@@ -1,58 +1,528 @@
-use abstutil::{prettyprint_usize, CmdArgs, Timer};
+use abstutil::{CmdArgs, Timer};
+use geom::{Duration, Time};
 use map_model::Map;
 use rand::SeedableRng;
 use rand_xorshift::XorShiftRng;
-use sim::{AlertHandler, Scenario, Sim, SimFlags};
+use sim::{
+    AlertHandler, CsvObserver, JsonObserver, PandemicConfig, PandemicObserver, PandemicSummary,
+    PercentileObserver, Scenario, SeirComparison, Sim, SimFlags, StdoutObserver,
+};
+use std::time::Instant;
 
 // This is specialized to experiment with running the pandemic model over long time periods.
 // Original functionality for profiling and debugging gridlock have been removed.
 
+// How often observers record the state of the pandemic model
+const OBSERVE_FREQUENCY: Duration = Duration::const_seconds(3600.0);
+// How often to report progress, in real time
+const PROGRESS_FREQUENCY: Duration = Duration::const_seconds(10.0);
+
 fn main() {
     let mut args = CmdArgs::new();
-    let num_days = args
-        .optional_parse("--days", |s| s.parse::<usize>())
+    let days = args.optional_parse("--days", |s| s.parse::<usize>());
+    // Chains different scenarios together, like --pattern=5xweekday,2xweekend@0.5. Each segment
+    // repeats a scenario for some days. The optional @ suffix is the fraction of people who make
+    // any trips on those days.
+    let pattern = args
+        .optional("--pattern")
+        .map(|raw| match parse_pattern(&raw) {
+            Ok(segments) => segments,
+            Err(err) => panic!("Bad --pattern={}: {}", raw, err),
+        });
+    // A JSON file with any PandemicConfig fields to override. Flags like --import_per_day
+    // override the file.
+    let params = args.optional("--params");
+    let import_per_day = args.optional_parse("--import_per_day", |s| s.parse::<usize>());
+    // Runs the whole experiment once per scenario, so this multiplies the runtime by the number
+    // of scenarios.
+    let compare_scenarios = args.optional("--compare_scenarios");
+    if pattern.is_some() && (days.is_some() || compare_scenarios.is_some()) {
+        panic!("--pattern can't be combined with --days or --compare_scenarios");
+    }
+    // Runs each scenario this many times, with a different seed for the pandemic model each time.
+    // Trips are the same every time.
+    let num_seeds = args
+        .optional_parse("--seeds", |s| s.parse::<u8>())
         .unwrap_or(1);
+    assert!(num_seeds > 0);
+    // The first seed for the pandemic model, in hex. By default, every invocation is the same.
+    // --random_seed picks one and prints it, so the run can be repeated with --seed.
+    let fixed_seed = args.optional("--seed").map(|raw| match parse_seed(&raw) {
+        Ok(seed) => seed,
+        Err(err) => panic!("Bad --seed={}: {}", raw, err),
+    });
+    let random_seed = args.enabled("--random_seed");
+    if fixed_seed.is_some() && random_seed {
+        panic!("Pass --seed or --random_seed, not both");
+    }
+    let quiet = args.enabled("--quiet");
+    // Just run the traffic simulation, as a baseline
+    let no_pandemic = args.enabled("--no_pandemic");
+    // Once nobody's exposed or infected for this many hours, stop the run early.
+    let stop_grace_period = args
+        .optional_parse("--stop_when_no_infections", |s| s.parse::<usize>())
+        .map(Duration::hours);
+    let mut observers: Vec<Box<dyn PandemicObserver>> = Vec::new();
+    if args.enabled("--print") {
+        observers.push(Box::new(StdoutObserver::new(OBSERVE_FREQUENCY)));
+    }
+    if let Some(path) = args.optional("--csv") {
+        observers.push(Box::new(if compare_scenarios.is_some() || num_seeds > 1 {
+            CsvObserver::new_multi_run(&path, OBSERVE_FREQUENCY).unwrap()
+        } else {
+            CsvObserver::new(&path, OBSERVE_FREQUENCY).unwrap()
+        }));
+    }
+    // Percentiles of each compartment across all runs, most useful with --seeds
+    if let Some(path) = args.optional("--percentiles") {
+        observers.push(Box::new(PercentileObserver::new(path, OBSERVE_FREQUENCY)));
+    }
+    // Needs the map name, so the observer is created after loading
+    let json_path = args.optional("--json");
+    // Instead of running anything, compare two CSVs from earlier single runs with --csv, like
+    // --compare_csv=baseline.csv,masks.csv. Per-time differences go to --diff_csv.
+    let compare_csv = args.optional("--compare_csv").map(|raw| {
+        let paths: Vec<String> = raw.split(',').map(|x| x.to_string()).collect();
+        if paths.len() != 2 {
+            panic!("Bad --compare_csv={}: needs exactly two files", raw);
+        }
+        paths
+    });
+    let diff_csv = args.optional("--diff_csv");
+    let scenarios: Vec<String> = match compare_scenarios {
+        Some(list) => list.split(',').map(|x| x.to_string()).collect(),
+        None => vec!["weekday".to_string()],
+    };
     args.done();
+    if let Some(paths) = compare_csv {
+        compare(&paths[0], &paths[1], diff_csv);
+        return;
+    }
+    let num_days = if let Some(ref segments) = pattern {
+        segments.iter().map(|(days, _, _)| *days).sum()
+    } else {
+        days.unwrap_or(1)
+    };
+    // With a pattern, each run is the whole pattern
+    let scenarios = match pattern {
+        Some(ref segments) => vec![segments
+            .iter()
+            .map(|(days, name, _)| format!("{}x{}", days, name))
+            .collect::<Vec<_>>()
+            .join(",")],
+        None => scenarios,
+    };
 
     let mut sim_flags = SimFlags::synthetic_test("montlake", "pandemic");
-    sim_flags.opts.enable_pandemic_model = Some(XorShiftRng::from_seed([sim_flags.rng_seed; 16]));
+    let mut config: PandemicConfig = if let Some(path) = params {
+        match abstutil::maybe_read_json(path.clone(), &mut Timer::throwaway()) {
+            Ok(config) => config,
+            Err(err) => panic!("Bad --params={}: {}", path, err),
+        }
+    } else {
+        PandemicConfig::default()
+    };
+    if let Some(n) = import_per_day {
+        config = config.import_per_day(n);
+    }
+    sim_flags.opts.pandemic_config = config;
     // Less spam
     sim_flags.opts.alerts = AlertHandler::Silence;
     let mut timer = Timer::new("setup headless");
-    let (mut map, _, mut rng) = sim_flags.load(&mut timer);
+    let (mut map, _, _) = sim_flags.load(&mut timer);
     map.hack_override_offstreet_spots(num_days);
-    // After the map changes, have to re-create the (empty) Sim, because things like
-    // ParkingSimState are out of sync.
-    let mut sim = Sim::new(&map, sim_flags.opts.clone(), &mut timer);
-
-    let base_scenario: Scenario = abstutil::read_binary(
-        abstutil::path_scenario(map.get_name(), "weekday"),
-        &mut timer,
-    );
-    base_scenario
-        .repeat_days(num_days)
-        .instantiate(&mut sim, &map, &mut rng, &mut timer);
+    // Don't find out about a typo after running the first scenario for days
+    let mut needed: Vec<&String> = scenarios.iter().collect();
+    if let Some(ref segments) = pattern {
+        needed = segments.iter().map(|(_, name, _)| name).collect();
+    }
+    for name in needed {
+        let path = abstutil::path_scenario(map.get_name(), name);
+        if !abstutil::file_exists(path.clone()) {
+            panic!("Scenario {} doesn't exist", path);
+        }
+    }
     timer.done();
+    let base_seed = if random_seed {
+        let seed = rand::random::<u128>();
+        println!("Using --seed={:x}", seed);
+        seed
+    } else {
+        fixed_seed.unwrap_or_else(|| u128::from_le_bytes([sim_flags.rng_seed; 16]))
+    };
+    if let Some(path) = json_path {
+        observers.push(Box::new(JsonObserver::new(
+            path,
+            OBSERVE_FREQUENCY,
+            map.get_name().to_string(),
+            base_seed,
+            sim_flags.opts.pandemic_config.clone(),
+        )));
+    }
+
+    for (name, seed) in scenarios
+        .iter()
+        .flat_map(|name| (0..num_seeds).map(move |i| (name, base_seed.wrapping_add(i.into()))))
+    {
+        let run_name = if num_seeds > 1 {
+            format!("{}_seed{:x}", name, seed)
+        } else {
+            name.to_string()
+        };
+        let mut timer = Timer::new(format!("setup {}", run_name));
+        // After the map changes, have to re-create the (empty) Sim, because things like
+        // ParkingSimState are out of sync. Every scenario also starts from scratch, with the same
+        // seed for everything but the pandemic model.
+        let mut opts = sim_flags.opts.clone();
+        if !no_pandemic {
+            opts.enable_pandemic_model = Some(pandemic_rng(seed));
+        }
+        let mut sim = Sim::new(&map, opts, &mut timer);
+        // Observers and the summary quietly do nothing without the model
+        assert_eq!(sim.get_pandemic_model().is_some(), !no_pandemic);
+        let scenario = if let Some(ref segments) = pattern {
+            make_pattern(&map, segments, &mut sim_flags.make_rng(), &mut timer)
+        } else {
+            let scenario: Scenario =
+                abstutil::read_binary(abstutil::path_scenario(map.get_name(), name), &mut timer);
+            scenario.repeat_days(num_days)
+        };
+        scenario.instantiate(&mut sim, &map, &mut sim_flags.make_rng(), &mut timer);
+        timer.done();
+
+        for o in observers.iter_mut() {
+            o.start_run(&run_name);
+        }
+        let mut progress = if quiet {
+            None
+        } else {
+            Some(Progress::new(
+                Time::START_OF_DAY + Duration::hours(24 * num_days),
+            ))
+        };
+        let summary = run_experiment(
+            &map,
+            &mut sim,
+            &mut observers,
+            &mut progress,
+            stop_grace_period,
+        );
+        for o in observers.iter_mut() {
+            o.end_run(&sim, &map, summary.as_ref());
+        }
+        if let Some(s) = summary {
+            print_summary(&run_name, &s);
+        }
+    }
+}
+
+// The whole 128 bits of the pandemic model's seed, in hex, optionally starting with 0x
+fn parse_seed(raw: &str) -> Result<u128, String> {
+    u128::from_str_radix(raw.trim_start_matches("0x"), 16).map_err(|err| err.to_string())
+}
+
+fn pandemic_rng(seed: u128) -> XorShiftRng {
+    XorShiftRng::from_seed(seed.to_le_bytes())
+}
+
+// Parses something like "5xweekday,2xweekend@0.5" into (days, scenario name, mobility)
+fn parse_pattern(raw: &str) -> Result<Vec<(usize, String, f64)>, String> {
+    let mut segments = Vec::new();
+    for segment in raw.split(',') {
+        let (rest, mobility) = match segment.find('@') {
+            Some(idx) => {
+                let mobility = segment[idx + 1..]
+                    .parse::<f64>()
+                    .map_err(|_| format!("{}: {} isn't a number", segment, &segment[idx + 1..]))?;
+                if !(0.0..=1.0).contains(&mobility) {
+                    return Err(format!("{}: mobility must be between 0 and 1", segment));
+                }
+                (&segment[..idx], mobility)
+            }
+            None => (segment, 1.0),
+        };
+        let idx = rest
+            .find('x')
+            .ok_or_else(|| format!("{}: should look like 5xweekday", segment))?;
+        let days = rest[..idx]
+            .parse::<usize>()
+            .map_err(|_| format!("{}: {} isn't a number of days", segment, &rest[..idx]))?;
+        if days == 0 {
+            return Err(format!("{}: need at least one day", segment));
+        }
+        let name = &rest[idx + 1..];
+        if name.is_empty() {
+            return Err(format!("{}: missing the scenario name", segment));
+        }
+        segments.push((days, name.to_string(), mobility));
+    }
+    Ok(segments)
+}
 
-    run_experiment(&map, &mut sim);
+fn make_pattern(
+    map: &Map,
+    segments: &[(usize, String, f64)],
+    rng: &mut XorShiftRng,
+    timer: &mut Timer,
+) -> Scenario {
+    let mut scenarios: Vec<(Scenario, f64)> = Vec::new();
+    for (_, name, mobility) in segments {
+        scenarios.push((
+            abstutil::read_binary(abstutil::path_scenario(map.get_name(), name), timer),
+            *mobility,
+        ));
+    }
+    let mut days = Vec::new();
+    for ((num_days, _, _), (scenario, mobility)) in segments.iter().zip(scenarios.iter()) {
+        for _ in 0..*num_days {
+            days.push((scenario, *mobility));
+        }
+    }
+    match Scenario::chain_days(days, rng) {
+        Ok(s) => s,
+        Err(err) => panic!("Can't build the pattern: {}", err),
+    }
 }
 
-fn run_experiment(map: &Map, sim: &mut Sim) {
+fn run_experiment(
+    map: &Map,
+    sim: &mut Sim,
+    observers: &mut [Box<dyn PandemicObserver>],
+    progress: &mut Option<Progress>,
+    stop_grace_period: Option<Duration>,
+) -> Option<PandemicSummary> {
     let timer = Timer::new("run sim until done");
-    sim.run_until_done(
+    let mut early_stop = stop_grace_period.map(EarlyStop::new);
+    let mut summary: Option<PandemicSummary> = None;
+    sim.run_until_done_or_stopped(
         &map,
-        |sim, _map| {
-            // This'll run every 30 sim seconds
-            if false {
-                println!(
-                    "At {}, {} infected",
-                    sim.time(),
-                    prettyprint_usize(sim.get_pandemic_model().unwrap().count_infected())
-                );
+        |sim, map| {
+            // This'll run every 30 sim seconds. Counting everybody is the expensive part, so only
+            // do it once here, and share the result.
+            PandemicSummary::update(&mut summary, sim);
+            for o in observers.iter_mut() {
+                o.observe(sim, map, summary.as_ref());
+            }
+            if let Some(ref mut p) = progress {
+                p.update(sim.time());
+            }
+
+            if let (Some(ref mut stop), Some(s)) = (&mut early_stop, &summary) {
+                let c = &s.final_counts;
+                return stop.should_stop(sim.time(), c.exposed + c.infected);
             }
+            false
         },
         None,
     );
     timer.done();
     println!("Done at {}", sim.time());
+    PandemicSummary::update(&mut summary, sim);
+    summary
+}
+
+// Once nobody's exposed or infected for the grace period, the epidemic has burned out and there's
+// no point simulating more.
+struct EarlyStop {
+    grace: Duration,
+    no_infections_since: Option<Time>,
+}
+
+impl EarlyStop {
+    fn new(grace: Duration) -> EarlyStop {
+        EarlyStop {
+            grace,
+            no_infections_since: None,
+        }
+    }
+
+    // Active infections count exposed and infected people.
+    fn should_stop(&mut self, now: Time, active_infections: usize) -> bool {
+        if active_infections > 0 {
+            self.no_infections_since = None;
+            return false;
+        }
+        let since = *self.no_infections_since.get_or_insert(now);
+        now - since >= self.grace
+    }
+}
+
+fn print_summary(name: &str, s: &PandemicSummary) {
+    let c = &s.final_counts;
+    println!("========== Summary of {} ==========", name);
+    println!(
+        "Peak infections: {} at {}",
+        abstutil::prettyprint_usize(s.peak_infected),
+        s.peak_time
+    );
+    println!("Total deaths: {}", abstutil::prettyprint_usize(c.dead));
+    println!(
+        "Attack rate: {:.1}% ({} of {} people ever infected)",
+        s.attack_rate() * 100.0,
+        abstutil::prettyprint_usize(s.cumulative_infections),
+        abstutil::prettyprint_usize(s.population)
+    );
+    match s.last_new_infection {
+        Some(t) => println!("Last new infection at {}", t),
+        None => println!("No new infections after the start"),
+    }
+    println!(
+        "Longest stretch without new infections: {}",
+        s.longest_without_infections
+    );
+    println!(
+        "Final: {} sane, {} exposed, {} infected, {} recovered, {} dead",
+        abstutil::prettyprint_usize(c.sane),
+        abstutil::prettyprint_usize(c.exposed),
+        abstutil::prettyprint_usize(c.infected),
+        abstutil::prettyprint_usize(c.recovered),
+        abstutil::prettyprint_usize(c.dead)
+    );
+    println!("====================================");
+}
+
+// Differences are the other run minus the baseline
+fn compare(baseline: &str, other: &str, diff_csv: Option<String>) {
+    let cmp = match SeirComparison::read(baseline, other) {
+        Ok(cmp) => cmp,
+        Err(err) => panic!("Can't compare {} and {}: {}", baseline, other, err),
+    };
+    println!("========== {} vs {} ==========", other, baseline);
+    println!(
+        "Peak infections: {} at {} vs {} at {} ({:+}, {:+.1} hours)",
+        abstutil::prettyprint_usize(cmp.other.peak_infected),
+        cmp.other.peak_time,
+        abstutil::prettyprint_usize(cmp.baseline.peak_infected),
+        cmp.baseline.peak_time,
+        cmp.peak_infected_difference(),
+        cmp.peak_shift().inner_seconds() / 3600.0
+    );
+    println!(
+        "Total deaths: {} vs {} ({:+})",
+        abstutil::prettyprint_usize(cmp.other.dead),
+        abstutil::prettyprint_usize(cmp.baseline.dead),
+        cmp.dead_difference()
+    );
+    println!(
+        "Attack rate: {:.1}% vs {:.1}% ({:+.1} points)",
+        cmp.other.attack_rate * 100.0,
+        cmp.baseline.attack_rate * 100.0,
+        cmp.attack_rate_difference() * 100.0
+    );
+    if let Some(path) = diff_csv {
+        cmp.write_csv(&path).unwrap();
+        println!("Wrote {}", path);
+    }
+}
+
+// Periodically prints how far along a run is, with a rough estimate of the real time left.
+struct Progress {
+    end: Time,
+    started: Instant,
+    last_print: Instant,
+}
+
+impl Progress {
+    fn new(end: Time) -> Progress {
+        Progress {
+            end,
+            started: Instant::now(),
+            last_print: Instant::now(),
+        }
+    }
+
+    fn update(&mut self, now: Time) {
+        if Duration::realtime_elapsed(self.last_print) < PROGRESS_FREQUENCY {
+            return;
+        }
+        self.last_print = Instant::now();
+
+        // Trips can run a bit past the last day
+        let done = ((now - Time::START_OF_DAY) / (self.end - Time::START_OF_DAY)).min(1.0);
+        let elapsed = Duration::realtime_elapsed(self.started);
+        let eta = if done > 0.0 {
+            format!("{}", elapsed * ((1.0 - done) / done))
+        } else {
+            "???".to_string()
+        };
+        println!(
+            "Progress: {} of {} ({:.1}%), about {} left",
+            now,
+            self.end,
+            done * 100.0,
+            eta
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{pandemic_rng, parse_pattern, parse_seed, EarlyStop};
+    use geom::{Duration, Time};
+    use rand::Rng;
+
+    // Steps like run_until_done, every 30 sim seconds, for some days. Returns when the run would
+    // stop.
+    fn run_days(days: usize, mut active_infections: impl FnMut(Time) -> usize) -> Time {
+        let mut stop = EarlyStop::new(Duration::hours(2));
+        let end = Time::START_OF_DAY + Duration::hours(24 * days);
+        let mut now = Time::START_OF_DAY;
+        while now < end {
+            if stop.should_stop(now, active_infections(now)) {
+                break;
+            }
+            now = now + Duration::seconds(30.0);
+        }
+        now
+    }
+
+    #[test]
+    fn test_parse_pattern() {
+        assert_eq!(
+            parse_pattern("5xweekday,2xweekend@0.5"),
+            Ok(vec![
+                (5, "weekday".to_string(), 1.0),
+                (2, "weekend".to_string(), 0.5)
+            ])
+        );
+        assert!(parse_pattern("weekday").is_err());
+        assert!(parse_pattern("0xweekday").is_err());
+        assert!(parse_pattern("2x").is_err());
+        assert!(parse_pattern("2xweekday@1.5").is_err());
+        assert!(parse_pattern("5xweekday,").is_err());
+    }
+
+    #[test]
+    fn test_seed() {
+        // Every bit of the seed matters
+        let seed = 0x0123_4567_89ab_cdef_fedc_ba98_7654_3210;
+        assert_eq!(parse_seed(&format!("{:x}", seed)), Ok(seed));
+        assert_eq!(parse_seed("0x2a"), Ok(42));
+        assert!(parse_seed("").is_err());
+        assert!(parse_seed("xyz").is_err());
+
+        let draws = |seed| {
+            let mut rng = pandemic_rng(seed);
+            (0..10).map(|_| rng.gen::<u64>()).collect::<Vec<_>>()
+        };
+        assert_eq!(draws(seed), draws(seed));
+        assert_ne!(draws(seed), draws(seed ^ (1 << 127)));
+    }
+
+    #[test]
+    fn test_early_stop() {
+        // Nobody's ever infected, so stop after the grace period instead of running for days
+        assert_eq!(run_days(3, |_| 0), Time::START_OF_DAY + Duration::hours(2));
+
+        // Somebody's always infected, so run the whole time
+        let end = Time::START_OF_DAY + Duration::hours(72);
+        assert_eq!(run_days(3, |_| 1), end);
+
+        // A new infection restarts the grace period
+        let flare_up = Time::START_OF_DAY + Duration::hours(1);
+        assert_eq!(
+            run_days(3, |now| if now == flare_up { 1 } else { 0 }),
+            flare_up + Duration::seconds(30.0) + Duration::hours(2)
+        );
+    }
 }
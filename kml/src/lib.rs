@@ -5,17 +5,35 @@ use quick_xml::Reader;
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
 pub struct ExtraShapes {
     pub shapes: Vec<ExtraShape>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
 pub struct ExtraShape {
     pub points: Vec<LonLat>,
     pub attributes: BTreeMap<String, String>,
 }
 
+impl ExtraShapes {
+    // For editing or diffing by hand. The binary format is much faster to load, so the importer
+    // sticks with that.
+    pub fn write_json(&self, path: String) {
+        abstutil::write_json(path, self);
+    }
+
+    pub fn read_json(path: String, timer: &mut Timer) -> ExtraShapes {
+        abstutil::read_json(path, timer)
+    }
+
+    // Combines datasets, like collisions and sidewalks. Shapes are only identified by their
+    // position in the list, so the other shapes just go after these.
+    pub fn merge(&mut self, other: ExtraShapes) {
+        self.shapes.extend(other.shapes);
+    }
+}
+
 pub fn load(
     path: &str,
     gps_bounds: &GPSBounds,
@@ -118,3 +136,58 @@ fn parse_pt(input: &str, gps_bounds: &GPSBounds) -> Option<LonLat> {
         None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shape(kind: &str) -> ExtraShape {
+        let mut attributes = BTreeMap::new();
+        attributes.insert("type".to_string(), kind.to_string());
+        ExtraShape {
+            points: vec![LonLat::new(-122.3086, 47.6384)],
+            attributes,
+        }
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut shapes = ExtraShapes {
+            shapes: vec![shape("collision"), shape("collision")],
+        };
+        shapes.merge(ExtraShapes {
+            shapes: vec![shape("sidewalk")],
+        });
+        assert_eq!(
+            shapes,
+            ExtraShapes {
+                shapes: vec![shape("collision"), shape("collision"), shape("sidewalk")],
+            }
+        );
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let mut attributes = BTreeMap::new();
+        attributes.insert("SW_WIDTH".to_string(), "60".to_string());
+        attributes.insert("type".to_string(), "sidewalk".to_string());
+        let shapes = ExtraShapes {
+            shapes: vec![ExtraShape {
+                points: vec![
+                    LonLat::new(-122.3086, 47.6384),
+                    LonLat::new(-122.30123456789, 47.6398765),
+                ],
+                attributes,
+            }],
+        };
+
+        // Unique to this process, so concurrent test runs don't clobber each other
+        let path =
+            std::env::temp_dir().join(format!("{}_test_extra_shapes.json", std::process::id()));
+        let path = path.to_str().unwrap().to_string();
+        shapes.write_json(path.clone());
+        let copy = ExtraShapes::read_json(path.clone(), &mut Timer::throwaway());
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(copy, shapes);
+    }
+}
@@ -21,6 +21,11 @@ pub(crate) use self::mechanics::{
     DrivingSimState, IntersectionSimState, ParkingSimState, WalkingSimState,
 };
 pub(crate) use self::pandemic::PandemicModel;
+pub use self::pandemic::{
+    read_seir_csv, ContactSpace, ContactTrace, CsvObserver, DrawLog, JsonObserver, PandemicConfig,
+    PandemicCounts, PandemicObserver, PandemicReport, PandemicRun, PandemicSummary,
+    PercentileObserver, SeirComparison, SeirHighlights, StdoutObserver,
+};
 pub(crate) use self::router::{ActionAtEnd, Router};
 pub(crate) use self::scheduler::{Command, Scheduler};
 pub use self::sim::{AgentProperties, AlertHandler, Sim, SimOptions};
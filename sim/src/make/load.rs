@@ -1,4 +1,4 @@
-use crate::{AlertHandler, Scenario, Sim, SimOptions};
+use crate::{AlertHandler, PandemicConfig, Scenario, Sim, SimOptions};
 use abstutil::CmdArgs;
 use geom::Duration;
 use map_model::{Map, MapEdits};
@@ -34,11 +34,13 @@ impl SimFlags {
                 dont_block_the_box: !args.enabled("--disable_block_the_box"),
                 recalc_lanechanging: !args.enabled("--disable_recalc_lc"),
                 break_turn_conflict_cycles: !args.enabled("--disable_break_turn_conflict_cycles"),
+                grade_affects_walking: args.enabled("--grade_affects_walking"),
                 enable_pandemic_model: if args.enabled("--pandemic") {
                     Some(XorShiftRng::from_seed([rng_seed; 16]))
                 } else {
                     None
                 },
+                pandemic_config: PandemicConfig::default(),
                 alerts: args
                     .optional("--alerts")
                     .map(|x| match x.as_ref() {
@@ -218,6 +218,65 @@ impl Scenario {
         self
     }
 
+    // Builds a schedule out of different kinds of days, like 5 weekdays and then 2 weekend days.
+    // Each entry is one day: a scenario with the same people as all the others, and the fraction
+    // of people who make any trips that day. Everybody else stays put for the day. Like
+    // repeat_days, this assumes everybody ends the day where they started.
+    pub fn chain_days(
+        days: Vec<(&Scenario, f64)>,
+        rng: &mut XorShiftRng,
+    ) -> Result<Scenario, String> {
+        if days.is_empty() {
+            return Err("No days to chain".to_string());
+        }
+        let first = days[0].0;
+        for (s, mobility) in &days {
+            if s.map_name != first.map_name {
+                return Err(format!(
+                    "{} is on {}, but {} is on {}",
+                    s.scenario_name, s.map_name, first.scenario_name, first.map_name
+                ));
+            }
+            if s.people.len() != first.people.len()
+                || s.people
+                    .iter()
+                    .zip(first.people.iter())
+                    .any(|(a, b)| a.id != b.id)
+            {
+                return Err(format!(
+                    "{} and {} don't have the same people",
+                    s.scenario_name, first.scenario_name
+                ));
+            }
+            if !(0.0..=1.0).contains(mobility) {
+                return Err(format!("Mobility {} isn't between 0 and 1", mobility));
+            }
+        }
+
+        let mut result = first.clone();
+        result.scenario_name = format!("{} days of {}", days.len(), first.scenario_name);
+        for person in &mut result.people {
+            person.trips.clear();
+        }
+        let mut offset = Duration::ZERO;
+        for (s, mobility) in days {
+            for (person, spec) in result.people.iter_mut().zip(s.people.iter()) {
+                // Always draw, so the mobility of one day doesn't affect who moves on later days
+                if !rng.gen_bool(mobility) {
+                    continue;
+                }
+                for trip in &spec.trips {
+                    person.trips.push(IndividTrip {
+                        depart: trip.depart + offset,
+                        trip: trip.trip.clone(),
+                    });
+                }
+            }
+            offset += Duration::hours(24);
+        }
+        Ok(result)
+    }
+
     pub fn count_parked_cars_per_bldg(&self) -> Counter<BuildingID> {
         let mut per_bldg = Counter::new();
         // Pass in a dummy RNG
@@ -635,3 +694,81 @@ impl PersonSpec {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Everybody makes one remote trip, departing at the given time of day
+    fn day(name: &str, map_name: &str, num_people: usize, depart: Time) -> Scenario {
+        let loc = OffMapLocation {
+            parcel_id: 0,
+            gps: LonLat::new(-122.3, 47.6),
+        };
+        Scenario {
+            scenario_name: name.to_string(),
+            map_name: map_name.to_string(),
+            people: (0..num_people)
+                .map(|id| PersonSpec {
+                    id: PersonID(id),
+                    orig_id: None,
+                    trips: vec![IndividTrip {
+                        depart,
+                        trip: SpawnTrip::Remote {
+                            from: loc.clone(),
+                            to: loc.clone(),
+                            trip_time: Duration::minutes(10),
+                            mode: TripMode::Walk,
+                        },
+                    }],
+                })
+                .collect(),
+            only_seed_buses: None,
+        }
+    }
+
+    fn departures(s: &Scenario) -> Vec<Time> {
+        s.people
+            .iter()
+            .flat_map(|p| p.trips.iter().map(|t| t.depart))
+            .collect()
+    }
+
+    #[test]
+    fn test_chain_days_mismatch() {
+        let mut rng = XorShiftRng::from_seed([42; 16]);
+        let weekday = day("weekday", "montlake", 3, Time::START_OF_DAY);
+        let elsewhere = day("weekend", "downtown", 3, Time::START_OF_DAY);
+        let fewer = day("weekend", "montlake", 2, Time::START_OF_DAY);
+        let mut renumbered = day("weekend", "montlake", 3, Time::START_OF_DAY);
+        renumbered.people[2].id = PersonID(7);
+
+        assert!(Scenario::chain_days(vec![(&weekday, 1.0), (&elsewhere, 1.0)], &mut rng).is_err());
+        assert!(Scenario::chain_days(vec![(&weekday, 1.0), (&fewer, 1.0)], &mut rng).is_err());
+        assert!(Scenario::chain_days(vec![(&weekday, 1.0), (&renumbered, 1.0)], &mut rng).is_err());
+        assert!(Scenario::chain_days(vec![(&weekday, 1.5)], &mut rng).is_err());
+        assert!(Scenario::chain_days(Vec::new(), &mut rng).is_err());
+    }
+
+    #[test]
+    fn test_chain_days() {
+        let mut rng = XorShiftRng::from_seed([42; 16]);
+        let eight_am = Time::START_OF_DAY + Duration::hours(8);
+        let weekday = day("weekday", "montlake", 3, eight_am);
+        let weekend = day("weekend", "montlake", 3, eight_am);
+
+        // Nobody moves on the middle day, and every other trip is shifted by whole days
+        let chained = Scenario::chain_days(
+            vec![(&weekday, 1.0), (&weekend, 0.0), (&weekday, 1.0)],
+            &mut rng,
+        )
+        .unwrap();
+        assert_eq!(chained.people.len(), 3);
+        let mut expected = Vec::new();
+        for _ in 0..3 {
+            expected.push(eight_am);
+            expected.push(eight_am + Duration::hours(48));
+        }
+        assert_eq!(departures(&chained), expected);
+    }
+}
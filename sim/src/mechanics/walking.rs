@@ -23,14 +23,17 @@ pub struct WalkingSimState {
     )]
     peds_per_traversable: MultiMap<Traversable, PedestrianID>,
     events: Vec<Event>,
+
+    grade_affects_walking: bool,
 }
 
 impl WalkingSimState {
-    pub fn new() -> WalkingSimState {
+    pub fn new(grade_affects_walking: bool) -> WalkingSimState {
         WalkingSimState {
             peds: BTreeMap::new(),
             peds_per_traversable: MultiMap::new(),
             events: Vec::new(),
+            grade_affects_walking,
         }
     }
 
@@ -78,7 +81,12 @@ impl WalkingSimState {
                 Line::new(driving_pos.pt(map), params.start.sidewalk_pos.pt(map)),
                 TimeInterval::new(now, now + TIME_TO_FINISH_BIKING),
             ),
-            _ => ped.crossing_state(params.start.sidewalk_pos.dist_along(), now, map),
+            _ => ped.crossing_state(
+                params.start.sidewalk_pos.dist_along(),
+                now,
+                map,
+                self.grade_affects_walking,
+            ),
         };
 
         scheduler.push(ped.state.get_end_time(), Command::UpdatePed(ped.id));
@@ -207,6 +215,7 @@ impl WalkingSimState {
                         &mut self.peds_per_traversable,
                         &mut self.events,
                         scheduler,
+                        self.grade_affects_walking,
                     ) {
                         scheduler.push(ped.state.get_end_time(), Command::UpdatePed(ped.id));
                     } else {
@@ -223,14 +232,19 @@ impl WalkingSimState {
                     &mut self.peds_per_traversable,
                     &mut self.events,
                     scheduler,
+                    self.grade_affects_walking,
                 ) {
                     scheduler.push(ped.state.get_end_time(), Command::UpdatePed(ped.id));
                     ped.total_blocked_time += now - blocked_since;
                 }
             }
             PedState::LeavingBuilding(b, _) => {
-                ped.state =
-                    ped.crossing_state(map.get_b(b).front_path.sidewalk.dist_along(), now, map);
+                ped.state = ped.crossing_state(
+                    map.get_b(b).front_path.sidewalk.dist_along(),
+                    now,
+                    map,
+                    self.grade_affects_walking,
+                );
                 scheduler.push(ped.state.get_end_time(), Command::UpdatePed(ped.id));
             }
             PedState::EnteringBuilding(bldg, _) => {
@@ -262,7 +276,12 @@ impl WalkingSimState {
                 self.peds.remove(&id);
             }
             PedState::FinishingBiking(ref spot, _, _) => {
-                ped.state = ped.crossing_state(spot.sidewalk_pos.dist_along(), now, map);
+                ped.state = ped.crossing_state(
+                    spot.sidewalk_pos.dist_along(),
+                    now,
+                    map,
+                    self.grade_affects_walking,
+                );
                 scheduler.push(ped.state.get_end_time(), Command::UpdatePed(ped.id));
             }
             PedState::WaitingForBus(_, _) => unreachable!(),
@@ -473,7 +492,13 @@ struct Pedestrian {
 }
 
 impl Pedestrian {
-    fn crossing_state(&self, start_dist: Distance, start_time: Time, map: &Map) -> PedState {
+    fn crossing_state(
+        &self,
+        start_dist: Distance,
+        start_time: Time,
+        map: &Map,
+        grade_affects_walking: bool,
+    ) -> PedState {
         let end_dist = if self.path.is_last_step() {
             self.goal.sidewalk_pos.dist_along()
         } else {
@@ -484,8 +509,22 @@ impl Pedestrian {
                 PathStep::Turn(t) => map.get_t(t).geom.length(),
             }
         };
+        let speed = if grade_affects_walking {
+            match self.path.current_step() {
+                PathStep::Lane(l) => {
+                    self.speed * grade_speed_multiplier(map.get_l(l).percent_grade(map))
+                }
+                // Walking the other way, so uphill becomes downhill
+                PathStep::ContraflowLane(l) => {
+                    self.speed * grade_speed_multiplier(-map.get_l(l).percent_grade(map))
+                }
+                PathStep::Turn(_) => self.speed,
+            }
+        } else {
+            self.speed
+        };
         let dist_int = DistanceInterval::new_walking(start_dist, end_dist);
-        let time_int = TimeInterval::new(start_time, start_time + dist_int.length() / self.speed);
+        let time_int = TimeInterval::new(start_time, start_time + dist_int.length() / speed);
         PedState::Crossing(dist_int, time_int)
     }
 
@@ -600,6 +639,7 @@ impl Pedestrian {
         peds_per_traversable: &mut MultiMap<Traversable, PedestrianID>,
         events: &mut Vec<Event>,
         scheduler: &mut Scheduler,
+        grade_affects_walking: bool,
     ) -> bool {
         if let PathStep::Turn(t) = self.path.next_step() {
             if !intersections.maybe_start_turn(
@@ -622,7 +662,7 @@ impl Pedestrian {
             PathStep::ContraflowLane(l) => map.get_l(l).length(),
             PathStep::Turn(_) => Distance::ZERO,
         };
-        self.state = self.crossing_state(start_dist, now, map);
+        self.state = self.crossing_state(start_dist, now, map, grade_affects_walking);
         peds_per_traversable.insert(self.path.current_step().as_traversable(), self.id);
         events.push(Event::AgentEntersTraversable(
             AgentID::Pedestrian(self.id),
@@ -702,3 +742,27 @@ fn find_crowds(
 
     (loners, crowds)
 }
+
+// Tobler's hiking function, relative to walking on flat ground. Grade is rise over run, positive
+// going uphill. Walking is fastest slightly downhill, at a 5% grade.
+pub fn grade_speed_multiplier(grade: f64) -> f64 {
+    (-3.5 * ((grade + 0.05).abs() - 0.05)).exp()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_grade_speed_multiplier() {
+        assert_eq!(grade_speed_multiplier(0.0), 1.0);
+        // A steep hill, like Queen Anne
+        let uphill = grade_speed_multiplier(0.1);
+        assert!((uphill - (-0.35_f64).exp()).abs() < 1e-9);
+        // A gentle descent is faster than flat, but a steep one isn't
+        assert!(grade_speed_multiplier(-0.05) > 1.0);
+        assert!(grade_speed_multiplier(-0.3) < 1.0);
+        // Going down the same hill is still faster than climbing it
+        assert!(grade_speed_multiplier(-0.1) > uphill);
+    }
+}
@@ -1,23 +1,68 @@
+mod observer;
 mod pandemic;
+mod rng;
 
 use geom::{Duration, Time};
-pub use pandemic::{Cmd, PandemicModel};
+pub use observer::{
+    read_seir_csv, CsvObserver, JsonObserver, PandemicCounts, PandemicObserver, PandemicReport,
+    PandemicRun, PandemicSummary, PercentileObserver, SeirComparison, SeirHighlights,
+    StdoutObserver,
+};
+pub use pandemic::{Cmd, ContactSpace, ContactTrace, PandemicConfig, PandemicModel};
 use rand::Rng;
 use rand_distr::{Distribution, Exp, Normal};
-use rand_xorshift::XorShiftRng;
+pub use rng::DrawLog;
+use rng::PandemicRng;
 use std::ops;
 
 #[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
 pub struct AnyTime(f64);
 
 impl AnyTime {
-    fn inner_seconds(&self) -> f64 {
-        self.0
-    }
-
     pub fn is_finite(&self) -> bool {
         self.0.is_finite()
     }
+
+    // A time that never comes. Used for events that aren't scheduled yet.
+    pub fn never() -> AnyTime {
+        AnyTime(std::f64::INFINITY)
+    }
+
+    pub fn is_never(&self) -> bool {
+        self.0 == std::f64::INFINITY
+    }
+
+    pub fn min(self, other: AnyTime) -> AnyTime {
+        if self <= other {
+            self
+        } else {
+            other
+        }
+    }
+
+    pub fn max(self, other: AnyTime) -> AnyTime {
+        if self >= other {
+            self
+        } else {
+            other
+        }
+    }
+
+    pub fn clamp(self, lo: AnyTime, hi: AnyTime) -> AnyTime {
+        assert!(lo <= hi);
+        self.max(lo).min(hi)
+    }
+
+    // Time can't represent anything before Time::START_OF_DAY or never happening.
+    pub fn try_into_time(self) -> Result<Time, String> {
+        if !self.is_finite() {
+            return Err(format!("{:?} isn't a finite time", self));
+        }
+        if self.0 < 0.0 {
+            return Err(format!("{:?} is before the start of the day", self));
+        }
+        Ok(Time::START_OF_DAY + Duration::seconds(self.0))
+    }
 }
 
 impl ops::Add<Duration> for AnyTime {
@@ -58,7 +103,10 @@ impl From<Time> for AnyTime {
 
 impl Into<Time> for AnyTime {
     fn into(self) -> Time {
-        Time::START_OF_DAY + Duration::seconds(self.inner_seconds())
+        match self.try_into_time() {
+            Ok(t) => t,
+            Err(err) => panic!("{}", err),
+        }
     }
 }
 
@@ -68,10 +116,16 @@ impl From<f64> for AnyTime {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum StateEvent {
     Exposition,
+    // Becoming infectious before symptoms start. Only happens when the config has a
+    // presymptomatic period.
+    Contagion,
     Incubation,
+    // No longer infectious, but not recovered yet. Only happens when the config has a convalescent
+    // period.
+    Clearance,
     Hospitalization,
     Recovery,
     Death,
@@ -80,53 +134,89 @@ pub enum StateEvent {
 #[derive(Debug, Clone)]
 pub struct Event {
     s: StateEvent,
-    p_hosp: f64,  // probability of people being hospitalized after infection
-    p_death: f64, // probability of dying after hospitalizaion
     t: AnyTime,
 }
 
 impl Event {
-    fn next(&self, now: AnyTime, rng: &mut XorShiftRng) -> State {
+    // The probabilities of hospitalization and death come from the config at the time of the
+    // transition, since they can change over the simulation.
+    fn next(&self, now: AnyTime, config: &PandemicConfig, rng: &mut PandemicRng) -> State {
+        let (p_hosp, p_death) = config.risk(now.into());
         match self.s {
-            StateEvent::Exposition => State::Exposed((
+            StateEvent::Exposition => {
+                let incubation = State::get_time_normal(State::T_INC, State::T_INC / 2.0, rng);
+                let presymptomatic = config.presymptomatic_period;
+                if presymptomatic == Duration::ZERO {
+                    State::Exposed((
+                        Event {
+                            s: StateEvent::Incubation,
+                            t: now + incubation,
+                        },
+                        now.into(),
+                    ))
+                } else {
+                    // Become infectious that long before symptoms, but not before exposure
+                    State::Exposed((
+                        Event {
+                            s: StateEvent::Contagion,
+                            t: now + (incubation - presymptomatic).max(Duration::ZERO),
+                        },
+                        now.into(),
+                    ))
+                }
+            }
+            StateEvent::Contagion => State::Presymptomatic((
                 Event {
                     s: StateEvent::Incubation,
-                    p_hosp: self.p_hosp,
-                    p_death: self.p_death,
-                    t: now + State::get_time_normal(State::T_INC, State::T_INC / 2.0, rng),
+                    t: now + config.presymptomatic_period,
                 },
                 now.into(),
             )),
             StateEvent::Incubation => {
-                if rng.gen_bool(self.p_death) {
-                    State::Infectious((
-                        Event {
-                            s: StateEvent::Recovery,
-                            p_hosp: self.p_hosp,
-                            p_death: self.p_death,
-                            t: now + State::get_time_normal(State::T_INF, State::T_INF / 2.0, rng),
-                        },
-                        now.into(),
-                    ))
+                if rng.gen_bool(p_death) {
+                    let recovery = State::get_time_normal(State::T_INF, State::T_INF / 2.0, rng);
+                    let convalescent = config.convalescent_period;
+                    if convalescent == Duration::ZERO {
+                        State::Infectious((
+                            Event {
+                                s: StateEvent::Recovery,
+                                t: now + recovery,
+                            },
+                            now.into(),
+                        ))
+                    } else {
+                        // Stop being infectious that long before recovering, but not before
+                        // symptoms start
+                        State::Infectious((
+                            Event {
+                                s: StateEvent::Clearance,
+                                t: now + (recovery - convalescent).max(Duration::ZERO),
+                            },
+                            now.into(),
+                        ))
+                    }
                 } else {
                     State::Infectious((
                         Event {
                             s: StateEvent::Hospitalization,
-                            p_hosp: self.p_hosp,
-                            p_death: self.p_death,
                             t: now + State::get_time_normal(State::T_INF, State::T_INF / 2.0, rng),
                         },
                         now.into(),
                     ))
                 }
             }
+            StateEvent::Clearance => State::Convalescent((
+                Event {
+                    s: StateEvent::Recovery,
+                    t: now + config.convalescent_period,
+                },
+                now.into(),
+            )),
             StateEvent::Hospitalization => {
-                if rng.gen_bool(self.p_hosp) {
+                if rng.gen_bool(p_hosp) {
                     State::Hospitalized((
                         Event {
                             s: StateEvent::Recovery,
-                            p_hosp: self.p_hosp,
-                            p_death: self.p_death,
                             t: now + State::get_time_normal(State::T_INF, State::T_INF / 2.0, rng),
                         },
                         now.into(),
@@ -135,8 +225,6 @@ impl Event {
                     State::Hospitalized((
                         Event {
                             s: StateEvent::Death,
-                            p_hosp: self.p_hosp,
-                            p_death: self.p_death,
                             t: now + State::get_time_normal(State::T_INF, State::T_INF / 2.0, rng),
                         },
                         now.into(),
@@ -153,7 +241,11 @@ impl Event {
 pub enum State {
     Sane((Event, Time)),
     Exposed((Event, Time)),
+    // Infectious, but no symptoms yet
+    Presymptomatic((Event, Time)),
     Infectious((Event, Time)),
+    // Not infectious anymore, but not recovered yet
+    Convalescent((Event, Time)),
     Hospitalized((Event, Time)),
     Recovered(Time),
     Dead(Time),
@@ -176,31 +268,35 @@ impl State {
         Self::E_RATIO
     }
 
-    fn new(p_hosp: f64, p_death: f64) -> Self {
+    fn new() -> Self {
         Self::Sane((
             Event {
                 s: StateEvent::Exposition,
-                p_hosp,
-                p_death,
-                t: AnyTime::from(std::f64::INFINITY),
+                t: AnyTime::never(),
             },
             Time::START_OF_DAY,
         ))
     }
 
-    fn get_time_exp(lambda: f64, rng: &mut XorShiftRng) -> geom::Duration {
+    // The chance that spending this long near an infectious person leads to exposure. Matches
+    // the draw in start().
+    fn exposure_probability(overlap: Duration) -> f64 {
+        1.0 - (-overlap.inner_seconds() * State::R_0 / State::T_INF).exp()
+    }
+
+    fn get_time_exp(lambda: f64, rng: &mut PandemicRng) -> geom::Duration {
         let normal = Exp::new(lambda).unwrap();
         Duration::seconds(normal.sample(rng))
     }
 
-    fn get_time_normal(mu: f64, sigma: f64, rng: &mut XorShiftRng) -> geom::Duration {
+    fn get_time_normal(mu: f64, sigma: f64, rng: &mut PandemicRng) -> geom::Duration {
         let normal = Normal::new(mu, sigma).unwrap();
         Duration::seconds(normal.sample(rng))
     }
 
     fn is_sane(&self) -> bool {
         match self {
-            State::Sane((ev, _)) => !ev.t.is_finite(),
+            State::Sane((ev, _)) => ev.t.is_never(),
             _ => false,
         }
     }
@@ -214,7 +310,21 @@ impl State {
 
     fn is_infectious(&self) -> bool {
         match self {
-            State::Infectious(_) | State::Hospitalized(_) => true,
+            State::Presymptomatic(_) | State::Infectious(_) | State::Hospitalized(_) => true,
+            _ => false,
+        }
+    }
+
+    fn is_hospitalized(&self) -> bool {
+        match self {
+            State::Hospitalized(_) => true,
+            _ => false,
+        }
+    }
+
+    fn is_convalescent(&self) -> bool {
+        match self {
+            State::Convalescent(_) => true,
             _ => false,
         }
     }
@@ -239,16 +349,34 @@ impl State {
             Self::Recovered(t)
             | Self::Dead(t)
             | Self::Exposed((_, t))
+            | Self::Presymptomatic((_, t))
             | Self::Infectious((_, t))
+            | Self::Convalescent((_, t))
             | Self::Hospitalized((_, t)) => Some(*t),
         }
     }
 
+    // Which event moved somebody into this state? Sane people haven't been through any.
+    pub fn entered_by(&self) -> Option<StateEvent> {
+        match self {
+            Self::Sane(_) => None,
+            Self::Exposed(_) => Some(StateEvent::Exposition),
+            Self::Presymptomatic(_) => Some(StateEvent::Contagion),
+            Self::Infectious(_) => Some(StateEvent::Incubation),
+            Self::Convalescent(_) => Some(StateEvent::Clearance),
+            Self::Hospitalized(_) => Some(StateEvent::Hospitalization),
+            Self::Recovered(_) => Some(StateEvent::Recovery),
+            Self::Dead(_) => Some(StateEvent::Death),
+        }
+    }
+
     pub fn get_event_time(&self) -> Option<AnyTime> {
         match self {
             Self::Sane((ev, _))
             | Self::Exposed((ev, _))
+            | Self::Presymptomatic((ev, _))
             | Self::Infectious((ev, _))
+            | Self::Convalescent((ev, _))
             | Self::Hospitalized((ev, _)) => Some(ev.t),
             Self::Recovered(_) | Self::Dead(_) => None,
         }
@@ -272,42 +400,68 @@ impl State {
     // }
 
     // TODO: not sure if we want an option here...
-    pub fn next_default(self, default: AnyTime, rng: &mut XorShiftRng) -> Option<Self> {
+    pub fn next_default(
+        self,
+        default: AnyTime,
+        config: &PandemicConfig,
+        rng: &mut PandemicRng,
+    ) -> Option<Self> {
         // TODO: when #![feature(bindings_after_at)] reaches stable
         // rewrite this part with it
         match self {
             Self::Sane((ev, _)) => Some(Self::Sane((ev, default.into()))),
-            Self::Exposed((ev, _)) => Some(ev.next(default, rng)),
-            Self::Infectious((ev, _)) => Some(ev.next(default, rng)),
-            Self::Hospitalized((ev, _)) => Some(ev.next(default, rng)),
+            Self::Exposed((ev, _)) => Some(ev.next(default, config, rng)),
+            Self::Presymptomatic((ev, _)) => Some(ev.next(default, config, rng)),
+            Self::Infectious((ev, _)) => Some(ev.next(default, config, rng)),
+            Self::Convalescent((ev, _)) => Some(ev.next(default, config, rng)),
+            Self::Hospitalized((ev, _)) => Some(ev.next(default, config, rng)),
             Self::Recovered(_) => Some(Self::Recovered(default.into())),
             Self::Dead(_) => Some(Self::Dead(default.into())),
         }
     }
 
     // TODO: not sure if we want an option here...
-    pub fn next(self, now: AnyTime, rng: &mut XorShiftRng) -> Option<Self> {
+    pub fn next(
+        self,
+        now: AnyTime,
+        config: &PandemicConfig,
+        rng: &mut PandemicRng,
+    ) -> Option<Self> {
         // TODO: when #![feature(bindings_after_at)] reaches stable
         // rewrite this part with it
         match self {
             Self::Sane((ev, t)) => Some(Self::Sane((ev, t))),
             Self::Exposed((ev, t)) => {
                 if ev.t <= now {
-                    Some(ev.next(now, rng))
+                    Some(ev.next(now, config, rng))
                 } else {
                     Some(Self::Exposed((ev, t)))
                 }
             }
+            Self::Presymptomatic((ev, t)) => {
+                if ev.t <= now {
+                    Some(ev.next(now, config, rng))
+                } else {
+                    Some(Self::Presymptomatic((ev, t)))
+                }
+            }
             Self::Infectious((ev, t)) => {
                 if ev.t <= now {
-                    Some(ev.next(now, rng))
+                    Some(ev.next(now, config, rng))
                 } else {
                     Some(Self::Infectious((ev, t)))
                 }
             }
+            Self::Convalescent((ev, t)) => {
+                if ev.t <= now {
+                    Some(ev.next(now, config, rng))
+                } else {
+                    Some(Self::Convalescent((ev, t)))
+                }
+            }
             Self::Hospitalized((ev, t)) => {
                 if ev.t <= now {
-                    Some(ev.next(now, rng))
+                    Some(ev.next(now, config, rng))
                 } else {
                     Some(Self::Hospitalized((ev, t)))
                 }
@@ -317,18 +471,23 @@ impl State {
         }
     }
 
+    // Whether exposure happens is drawn from transmission_rng, and everything after from
+    // progression_rng.
+    //
     // TODO: not sure if we want an option here... I guess here we want because we could have
     pub fn start(
         self,
         now: AnyTime,
         overlap: Duration,
-        rng: &mut XorShiftRng,
+        config: &PandemicConfig,
+        transmission_rng: &mut PandemicRng,
+        progression_rng: &mut PandemicRng,
     ) -> Result<Self, String> {
         // rewrite this part with it
         match self {
             Self::Sane((ev, t)) => {
-                if overlap >= Self::get_time_exp(State::R_0 / State::T_INF, rng) {
-                    Ok(ev.next(now, rng))
+                if overlap >= Self::get_time_exp(State::R_0 / State::T_INF, transmission_rng) {
+                    Ok(ev.next(now, config, progression_rng))
                 } else {
                     Ok(Self::Sane((ev, t)))
                 }
@@ -340,3 +499,30 @@ impl State {
     }
 }
 
+// A file for a test to write. The name is unique to this process, so concurrent test runs don't
+// clobber each other, and the file is removed when this is dropped, even if the test fails.
+#[cfg(test)]
+struct TempFile {
+    path: String,
+}
+
+#[cfg(test)]
+impl TempFile {
+    fn new(name: &str) -> TempFile {
+        let path = std::env::temp_dir().join(format!("{}_{}", std::process::id(), name));
+        TempFile::at(path.to_str().unwrap().to_string())
+    }
+
+    // For files somebody else names, like a CsvObserver's summary
+    fn at(path: String) -> TempFile {
+        TempFile { path }
+    }
+}
+
+#[cfg(test)]
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        // It might not have been written at all
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
@@ -0,0 +1,851 @@
+use crate::pandemic::{PandemicConfig, PandemicModel};
+use crate::Sim;
+use geom::{Duration, Time};
+use instant::Instant;
+use map_model::Map;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+// Something that wants to watch the pandemic model evolve. Meant to be called from the
+// run_until_done callback, which fires every few sim seconds; implementations decide how often
+// they actually care.
+//
+// Counting everybody's state is expensive for a whole city, so the caller keeps one summary of
+// the current run, updates it once per call, and shares it with every observer. It's None if the
+// pandemic model is disabled.
+pub trait PandemicObserver {
+    fn observe(&mut self, sim: &Sim, map: &Map, summary: Option<&PandemicSummary>);
+
+    // Called before each run when one observer watches several runs in a row, like when comparing
+    // scenarios. Time starts over with every run.
+    fn start_run(&mut self, _name: &str) {}
+
+    // Called after each run finishes, with the summary updated one last time.
+    fn end_run(&mut self, _sim: &Sim, _map: &Map, _summary: Option<&PandemicSummary>) {}
+}
+
+// Prints SEIR counts to STDOUT, at most once per frequency.
+pub struct StdoutObserver {
+    frequency: Duration,
+    next_time: Time,
+}
+
+impl StdoutObserver {
+    pub fn new(frequency: Duration) -> StdoutObserver {
+        StdoutObserver {
+            frequency,
+            next_time: Time::START_OF_DAY,
+        }
+    }
+}
+
+impl PandemicObserver for StdoutObserver {
+    fn observe(&mut self, sim: &Sim, _: &Map, summary: Option<&PandemicSummary>) {
+        if sim.time() < self.next_time {
+            return;
+        }
+        self.next_time = sim.time() + self.frequency;
+
+        if let Some(s) = summary {
+            let c = &s.final_counts;
+            println!(
+                "At {}: {} sane, {} exposed, {} infected, {} recovered, {} dead",
+                sim.time(),
+                abstutil::prettyprint_usize(c.sane),
+                abstutil::prettyprint_usize(c.exposed),
+                abstutil::prettyprint_usize(c.infected),
+                abstutil::prettyprint_usize(c.recovered),
+                abstutil::prettyprint_usize(c.dead)
+            );
+        }
+    }
+
+    fn start_run(&mut self, name: &str) {
+        println!("Starting {}", name);
+        self.next_time = Time::START_OF_DAY;
+    }
+}
+
+// Writes one row of SEIR counts per frequency. Time is in seconds since the start of the
+// simulation. A PandemicSummary of each run goes to a second file, with _summary added to the
+// name.
+pub struct CsvObserver {
+    out: BufWriter<File>,
+    summary_out: BufWriter<File>,
+    frequency: Duration,
+    next_time: Time,
+    // If this is watching several runs, the name of the current one goes in the first column.
+    run_name: Option<String>,
+}
+
+impl CsvObserver {
+    pub fn new(path: &str, frequency: Duration) -> Result<CsvObserver, std::io::Error> {
+        CsvObserver::create(path, frequency, None)
+    }
+
+    // Combines several runs into one file, with an extra scenario column.
+    pub fn new_multi_run(path: &str, frequency: Duration) -> Result<CsvObserver, std::io::Error> {
+        CsvObserver::create(path, frequency, Some(String::new()))
+    }
+
+    fn create(
+        path: &str,
+        frequency: Duration,
+        run_name: Option<String>,
+    ) -> Result<CsvObserver, std::io::Error> {
+        let prefix = if run_name.is_some() { "scenario," } else { "" };
+        let mut out = BufWriter::new(File::create(path)?);
+        writeln!(out, "{}{}", prefix, CSV_HEADER)?;
+        let mut summary_out = BufWriter::new(File::create(summary_path(path))?);
+        writeln!(
+            summary_out,
+            "{}peak_infected,peak_time,cumulative_infections,population,last_new_infection,\
+             longest_without_infections,sane,exposed,infected,recovered,dead",
+            prefix
+        )?;
+        Ok(CsvObserver {
+            out,
+            summary_out,
+            frequency,
+            next_time: Time::START_OF_DAY,
+            run_name,
+        })
+    }
+}
+
+// foo.csv becomes foo_summary.csv
+fn summary_path(path: &str) -> String {
+    if path.ends_with(".csv") {
+        format!("{}_summary.csv", path.trim_end_matches(".csv"))
+    } else {
+        format!("{}_summary", path)
+    }
+}
+
+impl PandemicObserver for CsvObserver {
+    fn observe(&mut self, sim: &Sim, _: &Map, summary: Option<&PandemicSummary>) {
+        if sim.time() < self.next_time {
+            return;
+        }
+        self.next_time = sim.time() + self.frequency;
+
+        if let Some(s) = summary {
+            if let Some(ref name) = self.run_name {
+                write!(self.out, "{},", name).unwrap();
+            }
+            let c = &s.final_counts;
+            writeln!(
+                self.out,
+                "{},{},{},{},{},{}",
+                sim.time().inner_seconds(),
+                c.sane,
+                c.exposed,
+                c.infected,
+                c.recovered,
+                c.dead
+            )
+            .unwrap();
+        }
+    }
+
+    fn start_run(&mut self, name: &str) {
+        self.next_time = Time::START_OF_DAY;
+        if self.run_name.is_some() {
+            self.run_name = Some(name.to_string());
+        }
+    }
+
+    fn end_run(&mut self, _: &Sim, _: &Map, summary: Option<&PandemicSummary>) {
+        if let Some(s) = summary {
+            if let Some(ref name) = self.run_name {
+                write!(self.summary_out, "{},", name).unwrap();
+            }
+            let c = &s.final_counts;
+            writeln!(
+                self.summary_out,
+                "{},{},{},{},{},{},{},{},{},{},{}",
+                s.peak_infected,
+                s.peak_time.inner_seconds(),
+                s.cumulative_infections,
+                s.population,
+                s.last_new_infection
+                    .map(|t| t.inner_seconds().to_string())
+                    .unwrap_or_else(String::new),
+                s.longest_without_infections.inner_seconds(),
+                c.sane,
+                c.exposed,
+                c.infected,
+                c.recovered,
+                c.dead
+            )
+            .unwrap();
+        }
+        self.out.flush().unwrap();
+        self.summary_out.flush().unwrap();
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PandemicCounts {
+    pub time: Time,
+    pub sane: usize,
+    pub exposed: usize,
+    pub infected: usize,
+    pub recovered: usize,
+    pub dead: usize,
+}
+
+impl PandemicCounts {
+    pub fn total(&self) -> usize {
+        self.sane + self.exposed + self.infected + self.recovered + self.dead
+    }
+}
+
+// The highlights of a run. The peak is only as precise as how often this is updated, so do that
+// as often as possible, not just at the observation frequency.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PandemicSummary {
+    pub peak_infected: usize,
+    pub peak_time: Time,
+    // Everybody who's ever been exposed, including people seeded at the start
+    pub cumulative_infections: usize,
+    pub population: usize,
+    // When somebody was last newly exposed. None if nobody was after the summary started.
+    pub last_new_infection: Option<Time>,
+    // The longest stretch with nobody newly exposed, so far. Useful for deciding when an outbreak
+    // is over.
+    pub longest_without_infections: Duration,
+    // When the current stretch with nobody newly exposed started
+    pub quiet_since: Time,
+    // Includes the total deaths
+    pub final_counts: PandemicCounts,
+}
+
+impl PandemicSummary {
+    // Counts everybody once
+    pub fn new(time: Time, model: &PandemicModel) -> PandemicSummary {
+        let counts = model.count_all(time);
+        PandemicSummary {
+            peak_infected: counts.infected,
+            peak_time: time,
+            cumulative_infections: counts.total() - counts.sane,
+            population: counts.total(),
+            last_new_infection: None,
+            longest_without_infections: Duration::ZERO,
+            quiet_since: time,
+            final_counts: counts,
+        }
+    }
+
+    // Starts a summary if there isn't one yet. Does nothing if the pandemic model is disabled.
+    pub fn update(summary: &mut Option<PandemicSummary>, sim: &Sim) {
+        if let Some(model) = sim.get_pandemic_model() {
+            if let Some(s) = summary {
+                s.update_model(sim.time(), model);
+            } else {
+                *summary = Some(PandemicSummary::new(sim.time(), model));
+            }
+        }
+    }
+
+    pub fn update_model(&mut self, time: Time, model: &PandemicModel) {
+        let next = PandemicSummary::new(time, model);
+        if next.peak_infected > self.peak_infected {
+            self.peak_infected = next.peak_infected;
+            self.peak_time = time;
+        }
+        if next.cumulative_infections > self.cumulative_infections {
+            self.last_new_infection = Some(time);
+            self.quiet_since = time;
+        }
+        self.longest_without_infections =
+            self.longest_without_infections.max(time - self.quiet_since);
+        self.cumulative_infections = next.cumulative_infections;
+        self.population = next.population;
+        self.final_counts = next.final_counts;
+    }
+
+    // The fraction of the population ever infected
+    pub fn attack_rate(&self) -> f64 {
+        if self.population == 0 {
+            0.0
+        } else {
+            (self.cumulative_infections as f64) / (self.population as f64)
+        }
+    }
+}
+
+// Everything JsonObserver writes: the settings of the experiment, then the time series of each
+// run.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PandemicReport {
+    pub map: String,
+    // The pandemic model's seed for the first run, in hex, like headless --seed takes
+    pub rng_seed: String,
+    pub config: PandemicConfig,
+    pub runs: Vec<PandemicRun>,
+    // Real time spent so far, across all runs
+    pub runtime: Duration,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PandemicRun {
+    pub scenario: String,
+    pub counts: Vec<PandemicCounts>,
+    // Counts when the run ended, which might fall between two regular observations
+    pub final_counts: Option<PandemicCounts>,
+    pub summary: Option<PandemicSummary>,
+}
+
+// Writes a PandemicReport as JSON after every run, so results from earlier runs survive if a
+// later one is killed.
+pub struct JsonObserver {
+    path: String,
+    frequency: Duration,
+    next_time: Time,
+    report: PandemicReport,
+    started: Instant,
+}
+
+impl JsonObserver {
+    // The path must end in .json.
+    pub fn new(
+        path: String,
+        frequency: Duration,
+        map: String,
+        rng_seed: u128,
+        config: PandemicConfig,
+    ) -> JsonObserver {
+        JsonObserver {
+            path,
+            frequency,
+            next_time: Time::START_OF_DAY,
+            report: PandemicReport {
+                map,
+                rng_seed: format!("{:x}", rng_seed),
+                config,
+                runs: Vec::new(),
+                runtime: Duration::ZERO,
+            },
+            started: Instant::now(),
+        }
+    }
+
+    fn current_run(&mut self) -> &mut PandemicRun {
+        if self.report.runs.is_empty() {
+            self.start_run("");
+        }
+        self.report.runs.last_mut().unwrap()
+    }
+}
+
+impl PandemicObserver for JsonObserver {
+    fn observe(&mut self, sim: &Sim, _: &Map, summary: Option<&PandemicSummary>) {
+        if sim.time() < self.next_time {
+            return;
+        }
+        self.next_time = sim.time() + self.frequency;
+
+        if let Some(s) = summary {
+            self.current_run().counts.push(s.final_counts.clone());
+        }
+    }
+
+    fn start_run(&mut self, name: &str) {
+        self.next_time = Time::START_OF_DAY;
+        self.report.runs.push(PandemicRun {
+            scenario: name.to_string(),
+            counts: Vec::new(),
+            final_counts: None,
+            summary: None,
+        });
+    }
+
+    fn end_run(&mut self, _: &Sim, _: &Map, summary: Option<&PandemicSummary>) {
+        let run = self.current_run();
+        run.final_counts = summary.map(|s| s.final_counts.clone());
+        run.summary = summary.cloned();
+        self.report.runtime = Duration::realtime_elapsed(self.started);
+        abstutil::write_json(self.path.clone(), &self.report);
+    }
+}
+
+const CSV_HEADER: &str = "time,sane,exposed,infected,recovered,dead";
+
+// Reads back the SEIR counts written by a CsvObserver watching a single run.
+pub fn read_seir_csv(path: &str) -> Result<Vec<PandemicCounts>, String> {
+    let contents = std::fs::read_to_string(path).map_err(|err| format!("{}: {}", path, err))?;
+    let mut lines = contents.lines();
+    let header = lines.next().ok_or_else(|| format!("{} is empty", path))?;
+    if header != CSV_HEADER {
+        return Err(format!(
+            "{} doesn't have the header of a single run: {}",
+            path, header
+        ));
+    }
+
+    let mut all = Vec::new();
+    for (idx, line) in lines.enumerate() {
+        let bad = |problem: String| format!("{} line {}: {}", path, idx + 2, problem);
+        let cells: Vec<&str> = line.split(',').collect();
+        if cells.len() != 6 {
+            return Err(bad(format!("expected 6 columns, got {}", line)));
+        }
+        let time = cells[0]
+            .parse::<f64>()
+            .map_err(|err| bad(format!("{}: {}", cells[0], err)))?;
+        let mut values = Vec::new();
+        for cell in &cells[1..] {
+            values.push(
+                cell.parse::<usize>()
+                    .map_err(|err| bad(format!("{}: {}", cell, err)))?,
+            );
+        }
+        all.push(PandemicCounts {
+            time: Time::START_OF_DAY + Duration::seconds(time),
+            sane: values[0],
+            exposed: values[1],
+            infected: values[2],
+            recovered: values[3],
+            dead: values[4],
+        });
+    }
+    Ok(all)
+}
+
+// Two runs side-by-side, like with and without an intervention. Every difference is the other run
+// minus the baseline.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SeirComparison {
+    // Every time observed in either run. A run that stopped earlier or observed at different times
+    // has None there.
+    pub rows: Vec<(Time, Option<PandemicCounts>, Option<PandemicCounts>)>,
+    pub baseline: SeirHighlights,
+    pub other: SeirHighlights,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SeirHighlights {
+    pub peak_infected: usize,
+    // The first time the peak was observed
+    pub peak_time: Time,
+    // From the last observation
+    pub dead: usize,
+    pub attack_rate: f64,
+}
+
+impl SeirHighlights {
+    fn new(counts: &[PandemicCounts]) -> Option<SeirHighlights> {
+        let last = counts.last()?;
+        let mut peak = &counts[0];
+        for c in counts {
+            if c.infected > peak.infected {
+                peak = c;
+            }
+        }
+        let population = last.total();
+        Some(SeirHighlights {
+            peak_infected: peak.infected,
+            peak_time: peak.time,
+            dead: last.dead,
+            attack_rate: if population == 0 {
+                0.0
+            } else {
+                1.0 - (last.sane as f64) / (population as f64)
+            },
+        })
+    }
+}
+
+impl SeirComparison {
+    pub fn new(
+        baseline: Vec<PandemicCounts>,
+        other: Vec<PandemicCounts>,
+    ) -> Result<SeirComparison, String> {
+        let baseline_highlights =
+            SeirHighlights::new(&baseline).ok_or_else(|| "the baseline is empty".to_string())?;
+        let other_highlights =
+            SeirHighlights::new(&other).ok_or_else(|| "the other run is empty".to_string())?;
+
+        let mut per_time: BTreeMap<Time, (Option<PandemicCounts>, Option<PandemicCounts>)> =
+            BTreeMap::new();
+        for c in baseline {
+            per_time.entry(c.time).or_insert((None, None)).0 = Some(c);
+        }
+        for c in other {
+            per_time.entry(c.time).or_insert((None, None)).1 = Some(c);
+        }
+        Ok(SeirComparison {
+            rows: per_time.into_iter().map(|(t, (a, b))| (t, a, b)).collect(),
+            baseline: baseline_highlights,
+            other: other_highlights,
+        })
+    }
+
+    pub fn read(baseline_path: &str, other_path: &str) -> Result<SeirComparison, String> {
+        SeirComparison::new(read_seir_csv(baseline_path)?, read_seir_csv(other_path)?)
+    }
+
+    // Negative if the other run peaked earlier
+    pub fn peak_shift(&self) -> Duration {
+        self.other.peak_time - self.baseline.peak_time
+    }
+
+    pub fn peak_infected_difference(&self) -> isize {
+        (self.other.peak_infected as isize) - (self.baseline.peak_infected as isize)
+    }
+
+    pub fn dead_difference(&self) -> isize {
+        (self.other.dead as isize) - (self.baseline.dead as isize)
+    }
+
+    pub fn attack_rate_difference(&self) -> f64 {
+        self.other.attack_rate - self.baseline.attack_rate
+    }
+
+    // One row per time, with the counts of both runs and their difference. Cells are blank where
+    // a run has no observation.
+    pub fn write_csv(&self, path: &str) -> Result<(), std::io::Error> {
+        let mut out = BufWriter::new(File::create(path)?);
+        let mut header = vec!["time".to_string()];
+        for suffix in &["baseline", "other", "diff"] {
+            for name in &["sane", "exposed", "infected", "recovered", "dead"] {
+                header.push(format!("{}_{}", name, suffix));
+            }
+        }
+        writeln!(out, "{}", header.join(","))?;
+
+        for (time, baseline, other) in &self.rows {
+            let mut cells = vec![time.inner_seconds().to_string()];
+            for counts in &[baseline, other] {
+                match counts {
+                    Some(c) => cells.extend(compartments(c).iter().map(|x| x.to_string())),
+                    None => cells.extend(vec![String::new(); 5]),
+                }
+            }
+            if let (Some(a), Some(b)) = (baseline, other) {
+                for (x, y) in compartments(a).iter().zip(compartments(b).iter()) {
+                    cells.push(((*y as isize) - (*x as isize)).to_string());
+                }
+            } else {
+                cells.extend(vec![String::new(); 5]);
+            }
+            writeln!(out, "{}", cells.join(","))?;
+        }
+        out.flush()
+    }
+}
+
+// In the same order as the CSV columns
+fn compartments(c: &PandemicCounts) -> [usize; 5] {
+    [c.sane, c.exposed, c.infected, c.recovered, c.dead]
+}
+
+const PERCENTILES: [f64; 5] = [5.0, 25.0, 50.0, 75.0, 95.0];
+
+// Keeps the time series of every run in memory, then writes percentiles of each SEIR compartment
+// across runs, per time. Meant for running the same scenario with many seeds, to get uncertainty
+// bands. Rewritten after every run.
+pub struct PercentileObserver {
+    path: String,
+    frequency: Duration,
+    next_time: Time,
+    runs: Vec<Vec<PandemicCounts>>,
+}
+
+impl PercentileObserver {
+    pub fn new(path: String, frequency: Duration) -> PercentileObserver {
+        PercentileObserver {
+            path,
+            frequency,
+            next_time: Time::START_OF_DAY,
+            runs: Vec::new(),
+        }
+    }
+
+    fn write(&self) -> Result<(), std::io::Error> {
+        let mut out = BufWriter::new(File::create(&self.path)?);
+        let mut header = vec!["time".to_string(), "runs".to_string()];
+        for name in &["sane", "exposed", "infected", "recovered", "dead"] {
+            for p in &PERCENTILES {
+                header.push(format!("{}_p{}", name, p));
+            }
+        }
+        writeln!(out, "{}", header.join(","))?;
+
+        for (time, num_runs, values) in percentiles(&self.runs) {
+            let cells: Vec<String> = values.into_iter().map(|x| x.to_string()).collect();
+            writeln!(
+                out,
+                "{},{},{}",
+                time.inner_seconds(),
+                num_runs,
+                cells.join(",")
+            )?;
+        }
+        out.flush()
+    }
+}
+
+impl PandemicObserver for PercentileObserver {
+    fn observe(&mut self, sim: &Sim, _: &Map, summary: Option<&PandemicSummary>) {
+        if sim.time() < self.next_time {
+            return;
+        }
+        self.next_time = sim.time() + self.frequency;
+
+        if let Some(s) = summary {
+            if self.runs.is_empty() {
+                self.runs.push(Vec::new());
+            }
+            self.runs.last_mut().unwrap().push(s.final_counts.clone());
+        }
+    }
+
+    fn start_run(&mut self, _: &str) {
+        self.next_time = Time::START_OF_DAY;
+        self.runs.push(Vec::new());
+    }
+
+    fn end_run(&mut self, _: &Sim, _: &Map, _: Option<&PandemicSummary>) {
+        self.write().unwrap();
+    }
+}
+
+// For every time observed in any run, returns the number of runs with an observation then and the
+// percentiles of each compartment, in the same order as PandemicCounts. Runs that stopped early
+// just don't count at later times.
+fn percentiles(runs: &[Vec<PandemicCounts>]) -> Vec<(Time, usize, Vec<f64>)> {
+    let mut per_time: BTreeMap<Time, Vec<&PandemicCounts>> = BTreeMap::new();
+    for run in runs {
+        for counts in run {
+            per_time
+                .entry(counts.time)
+                .or_insert_with(Vec::new)
+                .push(counts);
+        }
+    }
+
+    per_time
+        .into_iter()
+        .map(|(time, all)| {
+            let mut values = Vec::new();
+            for compartment in vec![
+                all.iter().map(|c| c.sane).collect::<Vec<_>>(),
+                all.iter().map(|c| c.exposed).collect(),
+                all.iter().map(|c| c.infected).collect(),
+                all.iter().map(|c| c.recovered).collect(),
+                all.iter().map(|c| c.dead).collect(),
+            ] {
+                let mut sorted: Vec<f64> = compartment.into_iter().map(|x| x as f64).collect();
+                sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+                for p in &PERCENTILES {
+                    values.push(percentile(&sorted, *p));
+                }
+            }
+            (time, all.len(), values)
+        })
+        .collect()
+}
+
+// Linearly interpolates between the closest ranks. The input must be sorted and non-empty.
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    let rank = (p / 100.0) * ((sorted.len() - 1) as f64);
+    let lower = rank.floor() as usize;
+    let upper = rank.ceil() as usize;
+    sorted[lower] + (rank - (lower as f64)) * (sorted[upper] - sorted[lower])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pandemic::TempFile;
+    use crate::SimOptions;
+    use abstutil::Timer;
+    use rand::SeedableRng;
+    use rand_xorshift::XorShiftRng;
+
+    struct CountingObserver {
+        calls: usize,
+    }
+
+    impl PandemicObserver for CountingObserver {
+        fn observe(&mut self, _: &Sim, _: &Map, _: Option<&PandemicSummary>) {
+            self.calls += 1;
+        }
+    }
+
+    #[test]
+    fn test_observer_invoked() {
+        let map = Map::blank();
+        let mut sim = Sim::new(&map, SimOptions::new("test"), &mut Timer::throwaway());
+        let mut observer = CountingObserver { calls: 0 };
+        // Nothing's scheduled, so the sim is done after the first step.
+        sim.run_until_done(&map, |sim, map| observer.observe(sim, map, None), None);
+        assert_eq!(observer.calls, 1);
+    }
+
+    #[test]
+    fn test_json_report() {
+        let file = TempFile::new("test_json_report.json");
+        let path = file.path.clone();
+        let map = Map::blank();
+        let mut opts = SimOptions::new("test");
+        opts.enable_pandemic_model = Some(XorShiftRng::from_seed([42; 16]));
+        let sim = Sim::new(&map, opts, &mut Timer::throwaway());
+
+        let config = PandemicConfig::default().p_hosp(0.2);
+        let mut observer = JsonObserver::new(
+            path.clone(),
+            Duration::hours(1),
+            "blank".to_string(),
+            42,
+            config.clone(),
+        );
+        let summary = PandemicSummary::new(sim.time(), sim.get_pandemic_model().unwrap());
+        observer.start_run("weekday");
+        observer.observe(&sim, &map, Some(&summary));
+        observer.end_run(&sim, &map, Some(&summary));
+
+        let report: PandemicReport = abstutil::read_json(path, &mut Timer::throwaway());
+        let counts = PandemicCounts {
+            time: Time::START_OF_DAY,
+            sane: 0,
+            exposed: 0,
+            infected: 0,
+            recovered: 0,
+            dead: 0,
+        };
+        assert_eq!(report.map, "blank");
+        assert_eq!(report.rng_seed, "2a");
+        assert_eq!(report.config, config);
+        assert_eq!(
+            report.runs,
+            vec![PandemicRun {
+                scenario: "weekday".to_string(),
+                counts: vec![counts.clone()],
+                final_counts: Some(counts.clone()),
+                summary: Some(PandemicSummary {
+                    peak_infected: 0,
+                    peak_time: Time::START_OF_DAY,
+                    cumulative_infections: 0,
+                    population: 0,
+                    last_new_infection: None,
+                    longest_without_infections: Duration::ZERO,
+                    quiet_since: Time::START_OF_DAY,
+                    final_counts: counts,
+                }),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_csv_multi_run() {
+        let file = TempFile::new("test_csv_multi_run.csv");
+        let _summary_file = TempFile::at(summary_path(&file.path));
+        let path = file.path.as_str();
+        let map = Map::blank();
+        let mut opts = SimOptions::new("test");
+        opts.enable_pandemic_model = Some(XorShiftRng::from_seed([42; 16]));
+        let sim = Sim::new(&map, opts, &mut Timer::throwaway());
+
+        {
+            let summary = PandemicSummary::new(sim.time(), sim.get_pandemic_model().unwrap());
+            let mut observer = CsvObserver::new_multi_run(path, Duration::hours(1)).unwrap();
+            for name in &["weekday", "weekend"] {
+                observer.start_run(name);
+                // Only the first observation in each run is recorded
+                observer.observe(&sim, &map, Some(&summary));
+                observer.observe(&sim, &map, Some(&summary));
+            }
+        }
+
+        let contents = std::fs::read_to_string(path).unwrap();
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(
+            lines,
+            vec![
+                "scenario,time,sane,exposed,infected,recovered,dead",
+                "weekday,0,0,0,0,0,0",
+                "weekend,0,0,0,0,0,0",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_percentiles() {
+        let counts = |hour: usize, infected: usize| PandemicCounts {
+            time: Time::START_OF_DAY + Duration::hours(hour),
+            sane: 100 - infected,
+            exposed: 0,
+            infected,
+            recovered: 0,
+            dead: 0,
+        };
+        // The last run stopped early
+        let runs = vec![
+            vec![counts(0, 10), counts(1, 50)],
+            vec![counts(0, 30), counts(1, 20)],
+            vec![counts(0, 20)],
+        ];
+        let result = percentiles(&runs);
+        assert_eq!(result.len(), 2);
+
+        // The median is the 3rd of the 5 percentiles for each compartment. Infected is third.
+        let (time, num_runs, values) = &result[0];
+        assert_eq!(*time, Time::START_OF_DAY);
+        assert_eq!(*num_runs, 3);
+        assert_eq!(values[2], 80.0);
+        assert_eq!(values[12], 20.0);
+        assert_eq!(values[11], 15.0);
+        assert_eq!(values[13], 25.0);
+
+        // Only two runs left, so the median falls in between
+        let (_, num_runs, values) = &result[1];
+        assert_eq!(*num_runs, 2);
+        assert_eq!(values[12], 35.0);
+    }
+
+    #[test]
+    fn test_compare_seir_csvs() {
+        let baseline = TempFile::new("test_compare_baseline.csv");
+        let other = TempFile::new("test_compare_other.csv");
+        let diff = TempFile::new("test_compare_diff.csv");
+        let (baseline_path, other_path, diff_path) = (
+            baseline.path.as_str(),
+            other.path.as_str(),
+            diff.path.as_str(),
+        );
+        // The other run peaks earlier and lower, and stops an hour sooner
+        std::fs::write(
+            baseline_path,
+            "time,sane,exposed,infected,recovered,dead\n0,90,0,10,0,0\n3600,40,0,50,9,1\n\
+             7200,30,0,20,47,3\n",
+        )
+        .unwrap();
+        std::fs::write(
+            other_path,
+            "time,sane,exposed,infected,recovered,dead\n0,90,0,10,0,0\n3600,85,0,5,10,0\n",
+        )
+        .unwrap();
+
+        let cmp = SeirComparison::read(baseline_path, other_path).unwrap();
+        assert_eq!(cmp.rows.len(), 3);
+        assert_eq!(cmp.peak_shift(), Duration::seconds(-3600.0));
+        assert_eq!(cmp.peak_infected_difference(), -40);
+        assert_eq!(cmp.dead_difference(), -3);
+        assert!((cmp.attack_rate_difference() - (0.15 - 0.7)).abs() < 1e-9);
+
+        cmp.write_csv(diff_path).unwrap();
+        let contents = std::fs::read_to_string(diff_path).unwrap();
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[1], "0,90,0,10,0,0,90,0,10,0,0,0,0,0,0,0");
+        assert_eq!(lines[2], "3600,40,0,50,9,1,85,0,5,10,0,45,0,-45,1,-1");
+        assert_eq!(lines[3], "7200,30,0,20,47,3,,,,,,,,,,");
+
+        assert!(read_seir_csv(diff_path).is_err());
+    }
+}
@@ -1,11 +1,16 @@
-use crate::pandemic::{AnyTime, State};
-use crate::{CarID, Event, OffMapLocation, Person, PersonID, Scheduler, TripPhaseType};
+use crate::pandemic::{AnyTime, DrawLog, PandemicCounts, PandemicRng, State, StateEvent};
+use crate::{
+    CarID, Command, Event, OffMapLocation, Person, PersonID, PersonState, Scheduler, TripPhaseType,
+};
 use geom::{Duration, Time};
 use map_model::{BuildingID, BusStopID};
-use rand::Rng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
 use rand_xorshift::XorShiftRng;
 use serde::{Deserialize, Serialize};
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::io::Write;
 
 // TODO This does not model transmission by surfaces; only person-to-person.
 // TODO If two people are in the same shared space indefinitely and neither leaves, we don't model
@@ -13,23 +18,229 @@ use std::collections::BTreeMap;
 
 #[derive(Clone)]
 pub struct PandemicModel {
-    pop: BTreeMap<PersonID, State>,
+    pop: Population,
 
     bldgs: SharedSpace<BuildingID>,
     remote_bldgs: SharedSpace<OffMapLocation>,
     bus_stops: SharedSpace<BusStopID>,
     buses: SharedSpace<CarID>,
     person_to_bus: BTreeMap<PersonID, CarID>,
+    person_to_stop: BTreeMap<PersonID, BusStopID>,
+    // Which remote building each person off-map or on a remote trip is in
+    person_to_remote: BTreeMap<PersonID, OffMapLocation>,
+    // Every state transition each person has gone through, in order
+    history: BTreeMap<PersonID, Vec<(StateEvent, Time)>>,
+    // Who exposed each person. People exposed from outside the map aren't here.
+    infected_by: BTreeMap<PersonID, PersonID>,
+    // Where each person starts the day. People starting off-map aren't here.
+    home: BTreeMap<PersonID, BuildingID>,
+    // Nothing enforces this yet; it's just tracked.
+    quarantined: BTreeSet<PersonID>,
+    // People can still be inside these buildings, but nobody's exposed there until the time when
+    // the building reopens.
+    closed_bldgs: BTreeMap<BuildingID, Time>,
 
-    rng: XorShiftRng,
+    config: PandemicConfig,
+    // Separate random streams, so that something consuming randomness in one doesn't shift every
+    // draw in the others. For example, importing cases doesn't change which contacts transmit.
+    // Whether contacts lead to exposure
+    transmission_rng: PandemicRng,
+    // How long each state lasts and where it leads
+    progression_rng: PandemicRng,
+    // Who gets picked for seeding, imported cases, and other interventions
+    intervention_rng: PandemicRng,
+    // Only filled out if the config asks for it. Grows with every contact, so only use this for
+    // debugging short runs.
+    contact_trace: Vec<ContactTrace>,
+    // How many contacts between any two people lasted how long, keyed by the start of each
+    // 5-minute bin, in minutes
+    contact_durations: BTreeMap<u32, usize>,
+    // The number of people infected after every change, in time order, so count_infected_at
+    // doesn't have to replay everybody's history
+    infected_over_time: Vec<(Time, usize)>,
     initialized: bool,
 }
 
+// Tunable parameters of the model. Start from PandemicConfig::default() and override what you
+// need, like PandemicConfig::default().p_hosp(0.2). When reading one from a file, missing fields
+// keep their default, and misspelled fields are an error instead of silently ignored.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct PandemicConfig {
+    // Probability of people being hospitalized after infection
+    pub(crate) p_hosp: f64,
+    // Probability of dying after hospitalization
+    pub(crate) p_death: f64,
+    // p_hosp and p_death can change over time, like when treatment improves. They start at the
+    // values above, then move linearly between each (time, p_hosp, p_death), holding steady
+    // after the last. Sorted by time.
+    pub(crate) risk_changes: Vec<(Time, f64, f64)>,
+    // How long people are infectious before symptoms start. Zero means people only become
+    // infectious when symptoms start.
+    pub(crate) presymptomatic_period: Duration,
+    // How long people who recover without hospitalization stay sick after they stop being
+    // infectious. Zero means people are infectious until they recover.
+    pub(crate) convalescent_period: Duration,
+    // Fraction of the population exposed at the start
+    pub(crate) initial_exposed_ratio: f64,
+    // Fraction of the initially exposed people who are already infectious
+    pub(crate) initial_infectious_ratio: f64,
+    // At the start of every day after the first, this many random sane people become exposed,
+    // modeling cases imported from outside the map.
+    pub(crate) import_per_day: usize,
+    // Record every contact between a sane and infectious person, for debugging
+    pub(crate) trace_contacts: bool,
+}
+
+impl Default for PandemicConfig {
+    fn default() -> PandemicConfig {
+        PandemicConfig {
+            p_hosp: 0.5,
+            p_death: 0.5,
+            risk_changes: Vec::new(),
+            presymptomatic_period: Duration::ZERO,
+            convalescent_period: Duration::ZERO,
+            initial_exposed_ratio: State::ini_exposed_ratio(),
+            initial_infectious_ratio: State::ini_infectious_ratio(),
+            import_per_day: 0,
+            trace_contacts: false,
+        }
+    }
+}
+
+impl PandemicConfig {
+    pub fn p_hosp(mut self, p: f64) -> PandemicConfig {
+        assert!((0.0..=1.0).contains(&p));
+        self.p_hosp = p;
+        self
+    }
+
+    pub fn p_death(mut self, p: f64) -> PandemicConfig {
+        assert!((0.0..=1.0).contains(&p));
+        self.p_death = p;
+        self
+    }
+
+    // Reach these probabilities at some time. Call in order of time.
+    pub fn risk_at(mut self, time: Time, p_hosp: f64, p_death: f64) -> PandemicConfig {
+        assert!((0.0..=1.0).contains(&p_hosp));
+        assert!((0.0..=1.0).contains(&p_death));
+        if let Some((last, _, _)) = self.risk_changes.last() {
+            assert!(time > *last);
+        } else {
+            assert!(time > Time::START_OF_DAY);
+        }
+        self.risk_changes.push((time, p_hosp, p_death));
+        self
+    }
+
+    // Returns (p_hosp, p_death) at some time.
+    pub(crate) fn risk(&self, now: Time) -> (f64, f64) {
+        let (mut t1, mut p_hosp1, mut p_death1) = (Time::START_OF_DAY, self.p_hosp, self.p_death);
+        for (t2, p_hosp2, p_death2) in &self.risk_changes {
+            if now < *t2 {
+                let pct = (now - t1) / (*t2 - t1);
+                return (
+                    p_hosp1 + pct * (p_hosp2 - p_hosp1),
+                    p_death1 + pct * (p_death2 - p_death1),
+                );
+            }
+            t1 = *t2;
+            p_hosp1 = *p_hosp2;
+            p_death1 = *p_death2;
+        }
+        (p_hosp1, p_death1)
+    }
+
+    pub fn presymptomatic_period(mut self, period: Duration) -> PandemicConfig {
+        assert!(period >= Duration::ZERO);
+        self.presymptomatic_period = period;
+        self
+    }
+
+    pub fn convalescent_period(mut self, period: Duration) -> PandemicConfig {
+        assert!(period >= Duration::ZERO);
+        self.convalescent_period = period;
+        self
+    }
+
+    pub fn initial_exposed_ratio(mut self, ratio: f64) -> PandemicConfig {
+        assert!((0.0..=1.0).contains(&ratio));
+        self.initial_exposed_ratio = ratio;
+        self
+    }
+
+    pub fn initial_infectious_ratio(mut self, ratio: f64) -> PandemicConfig {
+        assert!((0.0..=1.0).contains(&ratio));
+        self.initial_infectious_ratio = ratio;
+        self
+    }
+
+    pub fn import_per_day(mut self, n: usize) -> PandemicConfig {
+        self.import_per_day = n;
+        self
+    }
+
+    pub fn trace_contacts(mut self) -> PandemicConfig {
+        self.trace_contacts = true;
+        self
+    }
+}
+
+// Where two people met
+#[derive(Clone, Debug, PartialEq)]
+pub enum ContactSpace {
+    Building(BuildingID),
+    // The parcel ID of an off-map building
+    RemoteBuilding(usize),
+    BusStop(BusStopID),
+    Bus(CarID),
+}
+
+// One contact between a sane and an infectious person, and whether it led to exposure
+#[derive(Clone, Debug, PartialEq)]
+pub struct ContactTrace {
+    pub time: Time,
+    pub sane: PersonID,
+    pub infectious: PersonID,
+    pub space: ContactSpace,
+    pub overlap: Duration,
+    // The chance of exposure, given the overlap
+    pub probability: f64,
+    pub exposed: bool,
+}
+
+// One row of a line list: when did somebody enter each state? None if they never did.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PersonRecord {
+    pub person: PersonID,
+    pub exposed: Option<Time>,
+    pub infectious: Option<Time>,
+    // Stopped being infectious before recovering
+    pub cleared: Option<Time>,
+    pub hospitalized: Option<Time>,
+    pub recovered: Option<Time>,
+    pub dead: Option<Time>,
+}
+
+// Is somebody infected after this event? Matches count_infected.
+fn is_infected_after(ev: StateEvent) -> bool {
+    match ev {
+        StateEvent::Contagion
+        | StateEvent::Incubation
+        | StateEvent::Clearance
+        | StateEvent::Hospitalization => true,
+        StateEvent::Exposition | StateEvent::Recovery | StateEvent::Death => false,
+    }
+}
+
 // You can schedule callbacks in the future by doing scheduler.push(future time, one of these)
 #[derive(Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Clone, Debug)]
 pub enum Cmd {
     BecomeHospitalized(PersonID),
     BecomeQuarantined(PersonID),
+    // Expose this many random sane people, then do it again the next day
+    ImportCases(usize),
 }
 
 // TODO Pretend handle_event and handle_cmd also take in some object that lets you do things like:
@@ -41,44 +252,79 @@ pub enum Cmd {
 // from there.
 
 impl PandemicModel {
-    pub fn new(rng: XorShiftRng) -> PandemicModel {
+    pub fn new(config: PandemicConfig, mut rng: XorShiftRng) -> PandemicModel {
         PandemicModel {
-            pop: BTreeMap::new(),
+            pop: Population::new(),
 
             bldgs: SharedSpace::new(),
             remote_bldgs: SharedSpace::new(),
             bus_stops: SharedSpace::new(),
             buses: SharedSpace::new(),
             person_to_bus: BTreeMap::new(),
+            person_to_stop: BTreeMap::new(),
+            person_to_remote: BTreeMap::new(),
+            history: BTreeMap::new(),
+            infected_by: BTreeMap::new(),
+            home: BTreeMap::new(),
+            quarantined: BTreeSet::new(),
+            closed_bldgs: BTreeMap::new(),
 
-            rng,
+            config,
+            // Always split in the same order, so the streams only depend on the original seed
+            transmission_rng: PandemicRng::new(XorShiftRng::from_rng(&mut rng).unwrap()),
+            progression_rng: PandemicRng::new(XorShiftRng::from_rng(&mut rng).unwrap()),
+            intervention_rng: PandemicRng::new(XorShiftRng::from_rng(&mut rng).unwrap()),
+            contact_trace: Vec::new(),
+            contact_durations: BTreeMap::new(),
+            infected_over_time: Vec::new(),
             initialized: false,
         }
     }
 
     // Sorry, initialization order of simulations is still a bit messy. This'll be called at
     // Time::START_OF_DAY after all of the people have been created from a Scenario.
-    pub fn initialize(&mut self, population: &Vec<Person>, _scheduler: &mut Scheduler) {
+    pub fn initialize(&mut self, population: &Vec<Person>, scheduler: &mut Scheduler) {
         assert!(!self.initialized);
         self.initialized = true;
 
+        if population.is_empty() {
+            println!("WARNING: the pandemic model has nobody to infect");
+            return;
+        }
+
         // Seed initially infected people.
         // TODO the intial time is not well set. it should start "before"
         // the beginning of the day. Also
         for p in population {
-            let state = State::new(0.5, 0.5);
-            let state = if self.rng.gen_bool(State::ini_exposed_ratio()) {
+            let state = State::new();
+            let state = if self
+                .intervention_rng
+                .gen_bool(self.config.initial_exposed_ratio)
+            {
                 let next_state = state
                     .start(
                         AnyTime::from(Time::START_OF_DAY),
                         Duration::seconds(std::f64::MAX),
-                        &mut self.rng,
+                        &self.config,
+                        &mut self.intervention_rng,
+                        &mut self.progression_rng,
                     )
                     .unwrap();
-                let next_state = if self.rng.gen_bool(State::ini_infectious_ratio()) {
+                self.record_history(p.id, None, &next_state);
+                let next_state = if self
+                    .intervention_rng
+                    .gen_bool(self.config.initial_infectious_ratio)
+                {
+                    let prev = next_state.entered_by();
+                    let next_state = next_state
+                        .next_default(
+                            AnyTime::from(Time::START_OF_DAY),
+                            &self.config,
+                            &mut self.progression_rng,
+                        )
+                        .unwrap();
+                    self.record_history(p.id, prev, &next_state);
                     next_state
-                        .next_default(AnyTime::from(Time::START_OF_DAY), &mut self.rng)
-                        .unwrap()
                 } else {
                     next_state
                 };
@@ -87,6 +333,237 @@ impl PandemicModel {
                 state
             };
             self.pop.insert(p.id, state);
+            if let PersonState::Inside(b) = p.state {
+                self.home.insert(p.id, b);
+            }
+        }
+
+        if self.config.import_per_day > 0 {
+            scheduler.push(
+                Time::START_OF_DAY + Duration::hours(24),
+                Command::Pandemic(Cmd::ImportCases(self.config.import_per_day)),
+            );
+        }
+    }
+
+    // Forget everything that happened, keeping the config, so initialize can run again. Useful for
+    // running the same scenario many times with different seeds.
+    pub fn reset(&mut self, rng: XorShiftRng) {
+        *self = PandemicModel::new(self.config.clone(), rng);
+    }
+
+    // From now on, log every random draw the model makes. Pass the result of recorded_draws to
+    // replay_draws on another model to make it repeat exactly the same random choices.
+    pub fn record_draws(&mut self) {
+        self.transmission_rng.start_recording();
+        self.progression_rng.start_recording();
+        self.intervention_rng.start_recording();
+    }
+
+    // None if record_draws wasn't called
+    pub fn recorded_draws(&self) -> Option<DrawLog> {
+        Some(DrawLog {
+            transmission: self.transmission_rng.recorded()?,
+            progression: self.progression_rng.recorded()?,
+            intervention: self.intervention_rng.recorded()?,
+        })
+    }
+
+    // Instead of sampling, take every random draw from the log. Panics if the run needs more
+    // draws than were recorded.
+    pub fn replay_draws(&mut self, log: DrawLog) {
+        self.transmission_rng.replay(log.transmission);
+        self.progression_rng.replay(log.progression);
+        self.intervention_rng.replay(log.intervention);
+    }
+
+    // For every building somebody starts the day in, what fraction of those people have ever been
+    // infected (exposed or beyond)?
+    pub fn attack_rate_by_building(&self) -> BTreeMap<BuildingID, f64> {
+        let mut residents: BTreeMap<BuildingID, (usize, usize)> = BTreeMap::new();
+        for (person, b) in &self.home {
+            let entry = residents.entry(*b).or_insert((0, 0));
+            entry.0 += 1;
+            if self.history.contains_key(person) {
+                entry.1 += 1;
+            }
+        }
+        residents
+            .into_iter()
+            .map(|(b, (total, infected))| (b, (infected as f64) / (total as f64)))
+            .collect()
+    }
+
+    // For each person who has been through any transition, when did they enter each state?
+    pub fn line_list(&self) -> Vec<PersonRecord> {
+        self.history
+            .iter()
+            .map(|(person, events)| {
+                let mut record = PersonRecord {
+                    person: *person,
+                    exposed: None,
+                    infectious: None,
+                    cleared: None,
+                    hospitalized: None,
+                    recovered: None,
+                    dead: None,
+                };
+                for (ev, t) in events {
+                    let slot = match ev {
+                        StateEvent::Exposition => &mut record.exposed,
+                        // Symptoms don't matter if they were already infectious before
+                        StateEvent::Incubation if record.infectious.is_some() => continue,
+                        StateEvent::Contagion | StateEvent::Incubation => &mut record.infectious,
+                        StateEvent::Clearance => &mut record.cleared,
+                        StateEvent::Hospitalization => &mut record.hospitalized,
+                        StateEvent::Recovery => &mut record.recovered,
+                        StateEvent::Death => &mut record.dead,
+                    };
+                    *slot = Some(*t);
+                }
+                record
+            })
+            .collect()
+    }
+
+    // How many people were infected (presymptomatic, infectious, convalescent, or hospitalized) at
+    // some time in the past?
+    pub fn count_infected_at(&self, time: Time) -> usize {
+        match self
+            .infected_over_time
+            .binary_search_by(|(t, _)| t.cmp(&time))
+        {
+            Ok(idx) => self.infected_over_time[idx].1,
+            Err(0) => 0,
+            Err(idx) => self.infected_over_time[idx - 1].1,
+        }
+    }
+
+    // The exponential growth rate of current infections over the trailing window, per day. None
+    // if there were no infections at either end of the window, or the window starts before the
+    // simulation.
+    pub fn current_growth_rate(&self, window: Duration, now: Time) -> Option<f64> {
+        if window <= Duration::ZERO || now - Time::START_OF_DAY < window {
+            return None;
+        }
+        let before = self.count_infected_at(now - window);
+        let after = self.count_infected_at(now);
+        if before == 0 || after == 0 {
+            return None;
+        }
+        Some((after as f64 / before as f64).ln() / (window / Duration::hours(24)))
+    }
+
+    // How long until infections double at the current growth rate? None if they're not growing.
+    pub fn doubling_time(&self, window: Duration, now: Time) -> Option<Duration> {
+        let rate = self.current_growth_rate(window, now)?;
+        if rate <= 0.0 {
+            return None;
+        }
+        Some(Duration::hours(24) * (std::f64::consts::LN_2 / rate))
+    }
+
+    // Times are written as seconds since the start of the simulation. Blank cells mean the person
+    // never entered that state.
+    pub fn write_line_list(&self, path: &str) -> Result<(), std::io::Error> {
+        let mut f = File::create(path)?;
+        writeln!(
+            f,
+            "person,exposed,infectious,cleared,hospitalized,recovered,dead"
+        )?;
+        for r in self.line_list() {
+            let cells: Vec<String> = vec![
+                r.exposed,
+                r.infectious,
+                r.cleared,
+                r.hospitalized,
+                r.recovered,
+                r.dead,
+            ]
+            .into_iter()
+            .map(|t| t.map(|t| t.inner_seconds().to_string()).unwrap_or_default())
+            .collect();
+            writeln!(f, "{},{}", r.person.0, cells.join(","))?;
+        }
+        Ok(())
+    }
+
+    // Who exposed whom, and when, as (source, infectee, time). People exposed from outside the map,
+    // like the initial seeds and imported cases, have no source.
+    pub fn infection_tree(&self) -> Vec<(Option<PersonID>, PersonID, Time)> {
+        let mut edges = Vec::new();
+        for (person, events) in &self.history {
+            for (ev, t) in events {
+                if *ev == StateEvent::Exposition {
+                    edges.push((self.infected_by.get(person).cloned(), *person, *t));
+                }
+            }
+        }
+        edges
+    }
+
+    // An edge list for graph tools. Blank sources mean the infection came from outside the map.
+    pub fn write_infection_tree(&self, path: &str) -> Result<(), std::io::Error> {
+        let mut f = File::create(path)?;
+        writeln!(f, "source,infectee,time")?;
+        for (source, infectee, t) in self.infection_tree() {
+            writeln!(
+                f,
+                "{},{},{}",
+                source.map(|p| p.0.to_string()).unwrap_or_default(),
+                infectee.0,
+                t.inner_seconds()
+            )?;
+        }
+        Ok(())
+    }
+
+    // How long every contact between two people lasted, regardless of their states. Keyed by the
+    // start of each 5-minute bin, in minutes.
+    pub fn contact_duration_histogram(&self) -> &BTreeMap<u32, usize> {
+        &self.contact_durations
+    }
+
+    pub fn write_contact_duration_histogram(&self, path: &str) -> Result<(), std::io::Error> {
+        let mut f = File::create(path)?;
+        writeln!(f, "minutes,contacts")?;
+        for (bin, count) in &self.contact_durations {
+            writeln!(f, "{},{}", bin, count)?;
+        }
+        Ok(())
+    }
+
+    // Every contact between a sane and infectious person so far, if the config enables tracing.
+    pub fn contact_trace(&self) -> &Vec<ContactTrace> {
+        &self.contact_trace
+    }
+
+    // Only remembers something if the state actually changed.
+    fn record_history(&mut self, person: PersonID, prev: Option<StateEvent>, state: &State) {
+        let ev = state.entered_by();
+        if ev == prev {
+            return;
+        }
+        if let (Some(ev), Some(t)) = (ev, state.get_time()) {
+            self.history
+                .entry(person)
+                .or_insert_with(Vec::new)
+                .push((ev, t));
+
+            let was_infected = prev.map(is_infected_after).unwrap_or(false);
+            if was_infected != is_infected_after(ev) {
+                let before = self.infected_over_time.last().map(|(_, n)| *n).unwrap_or(0);
+                let after = if was_infected { before - 1 } else { before + 1 };
+                // Transitions happen in time order, so several at once only need one entry
+                match self.infected_over_time.last_mut() {
+                    Some((last, n)) if *last >= t => {
+                        *n = after;
+                    }
+                    _ => {
+                        self.infected_over_time.push((t, after));
+                    }
+                }
+            }
         }
     }
 
@@ -117,7 +594,11 @@ impl PandemicModel {
         self.pop
             .iter()
             .filter(|(_, state)| match state {
-                State::Infectious(_) | State::Hospitalized(_) => true,
+                // Convalescent people aren't infectious, but they're still sick
+                State::Presymptomatic(_)
+                | State::Infectious(_)
+                | State::Convalescent(_)
+                | State::Hospitalized(_) => true,
                 _ => false,
             })
             .count()
@@ -145,12 +626,72 @@ impl PandemicModel {
         // self.recovered.len()
     }
 
+    // Everybody is in exactly one of the counted states.
     pub fn count_total(&self) -> usize {
-        self.count_sane()
-            + self.count_exposed()
-            + self.count_infected()
-            + self.count_recovered()
-            + self.count_dead()
+        self.pop.iter().count()
+    }
+
+    // Same as calling each count method, but only looks at everybody once. Prefer this when
+    // several counts are needed, since the population can be a whole city.
+    pub fn count_all(&self, time: Time) -> PandemicCounts {
+        let mut counts = PandemicCounts {
+            time,
+            sane: 0,
+            exposed: 0,
+            infected: 0,
+            recovered: 0,
+            dead: 0,
+        };
+        for (_, state) in self.pop.iter() {
+            match state {
+                State::Sane(_) => {
+                    counts.sane += 1;
+                }
+                State::Exposed(_) => {
+                    counts.exposed += 1;
+                }
+                State::Presymptomatic(_)
+                | State::Infectious(_)
+                | State::Convalescent(_)
+                | State::Hospitalized(_) => {
+                    counts.infected += 1;
+                }
+                State::Recovered(_) => {
+                    counts.recovered += 1;
+                }
+                State::Dead(_) => {
+                    counts.dead += 1;
+                }
+            }
+        }
+        counts
+    }
+
+    fn people_in_state<'a>(
+        &'a self,
+        pred: impl Fn(&State) -> bool + 'a,
+    ) -> impl Iterator<Item = PersonID> + 'a {
+        self.pop
+            .iter()
+            .filter(move |(_, state)| pred(state))
+            .map(|(p, _)| p)
+    }
+
+    pub fn exposed_people(&self) -> impl Iterator<Item = PersonID> + '_ {
+        self.people_in_state(|state| state.is_exposed())
+    }
+
+    // Hospitalized people are also infectious.
+    pub fn infectious_people(&self) -> impl Iterator<Item = PersonID> + '_ {
+        self.people_in_state(|state| state.is_infectious())
+    }
+
+    pub fn hospitalized_people(&self) -> impl Iterator<Item = PersonID> + '_ {
+        self.people_in_state(|state| state.is_hospitalized())
+    }
+
+    pub fn dead_people(&self) -> impl Iterator<Item = PersonID> + '_ {
+        self.people_in_state(|state| state.is_dead())
     }
 
     pub fn handle_event(&mut self, now: Time, ev: &Event, scheduler: &mut Scheduler) {
@@ -158,42 +699,66 @@ impl PandemicModel {
 
         match ev {
             Event::PersonEntersBuilding(person, bldg) => {
+                // Aborted trips warp people straight to their destination, even mid-ride
+                self.end_transit(now, *person, scheduler);
+                if self.is_infectious(*person) {
+                    self.bldgs.infectious_present(now, *bldg);
+                }
                 self.bldgs.person_enters_space(now, *person, *bldg);
             }
             Event::PersonLeavesBuilding(person, bldg) => {
-                if let Some(others) = self.bldgs.person_leaves_space(now, *person, *bldg) {
-                    self.transmission(now, *person, others, scheduler);
+                if self.is_infectious(*person) {
+                    self.bldgs.infectious_present(now, *bldg);
+                }
+                if let Some(mut others) = self.bldgs.person_leaves_space(now, *person, *bldg) {
+                    if let Some(until) = self.closed_bldgs.get(bldg).cloned() {
+                        if now < until {
+                            return;
+                        }
+                        // Only the time since reopening counts
+                        for (_, overlap) in others.iter_mut() {
+                            *overlap = (*overlap).min(now - until);
+                        }
+                    }
+                    self.transmission(
+                        now,
+                        *person,
+                        others,
+                        ContactSpace::Building(*bldg),
+                        scheduler,
+                    );
                 } else {
                     panic!("{} left {}, but they weren't inside", person, bldg);
                 }
             }
             Event::PersonEntersRemoteBuilding(person, loc) => {
-                self.remote_bldgs
-                    .person_enters_space(now, *person, loc.clone());
+                self.end_transit(now, *person, scheduler);
+                self.enter_remote_bldg(now, *person, loc.clone(), scheduler);
             }
             Event::PersonLeavesRemoteBuilding(person, loc) => {
-                if let Some(others) =
-                    self.remote_bldgs
-                        .person_leaves_space(now, *person, loc.clone())
-                {
-                    self.transmission(now, *person, others, scheduler);
-                } else {
+                if self.person_to_remote.get(person) != Some(loc) {
                     panic!("{} left {:?}, but they weren't inside", person, loc);
                 }
+                self.leave_remote_bldg(now, *person, scheduler);
             }
             Event::TripPhaseStarting(_, p, _, tpt) => {
                 let person = *p;
+                let infectious = self.is_infectious(person);
                 match tpt {
                     TripPhaseType::WaitingForBus(_, stop) => {
+                        if infectious {
+                            self.bus_stops.infectious_present(now, *stop);
+                        }
                         self.bus_stops.person_enters_space(now, person, *stop);
+                        self.person_to_stop.insert(person, *stop);
                     }
                     TripPhaseType::RidingBus(_, stop, bus) => {
-                        let others = self
-                            .bus_stops
-                            .person_leaves_space(now, person, *stop)
-                            .unwrap();
-                        self.transmission(now, person, others, scheduler);
+                        assert_eq!(self.person_to_stop.get(&person), Some(stop));
+                        self.leave_bus_stop(now, person, scheduler);
 
+                        if infectious {
+                            self.buses.infectious_present(now, *bus);
+                        }
                         self.buses.person_enters_space(now, person, *bus);
                         self.person_to_bus.insert(person, *bus);
                     }
@@ -201,34 +766,138 @@ impl PandemicModel {
                         // A person can start walking for many reasons, but the only possible state
                         // transition after riding a bus is walking, so use this to detect the end
                         // of a bus ride.
-                        if let Some(car) = self.person_to_bus.remove(&person) {
-                            let others = self.buses.person_leaves_space(now, person, car).unwrap();
-                            self.transmission(now, person, others, scheduler);
-                        }
+                        self.leave_bus(now, person, scheduler);
                     }
                     _ => {
                         self.transition(now, person, scheduler);
                     }
                 }
             }
-            Event::PersonLeavesMap(_person, _, _, loc) => {
-                if let Some(_loc) = loc {
-                    // TODO Could make a SharedSpace for loc.parcel_id, representing buildings
-                    // off-map.
+            Event::PersonLeavesMap(person, _, _, loc) => {
+                self.end_transit(now, *person, scheduler);
+                // Spend the time off-map in the same remote building that remote trips and people
+                // starting the day off-map use.
+                if let Some(loc) = loc {
+                    self.enter_remote_bldg(now, *person, loc.clone(), scheduler);
+                } else {
+                    self.leave_remote_bldg(now, *person, scheduler);
                 }
             }
-            Event::PersonEntersMap(_person, _, _, loc) => {
-                if let Some(_loc) = loc {
-                    // TODO But we don't know how long the person spent at these parcels. They
-                    // could've taken tons of trips to other off-map parcels in between
-                    // PersonLeavesMap and PersonEntersMap.
-                }
+            Event::PersonEntersMap(person, _, _, _) => {
+                // Leave wherever the person actually is. Remote trips in between can move them, and
+                // scenarios can have them come back from a different parcel than they left to.
+                self.leave_remote_bldg(now, *person, scheduler);
             }
             _ => {}
         }
     }
 
-    pub fn handle_cmd(&mut self, _now: Time, cmd: Cmd, _scheduler: &mut Scheduler) {
+    // When was an infectious person last inside this space? Anything left behind on surfaces
+    // might still be there. Only entering and leaving counts, so this lags for people who become
+    // infectious while inside.
+    pub fn last_infectious_present(&self, space: &ContactSpace) -> Option<Time> {
+        match space {
+            ContactSpace::Building(b) => self.bldgs.last_infectious_present(b),
+            ContactSpace::RemoteBuilding(parcel_id) => self
+                .remote_bldgs
+                .last_infectious
+                .iter()
+                .filter(|(loc, _)| loc.parcel_id == *parcel_id)
+                .map(|(_, t)| *t)
+                .max(),
+            ContactSpace::BusStop(stop) => self.bus_stops.last_infectious_present(stop),
+            ContactSpace::Bus(car) => self.buses.last_infectious_present(car),
+        }
+    }
+
+    // Aborted trips can end while somebody's waiting for a bus or riding one. They're not stuck
+    // there forever.
+    fn end_transit(&mut self, now: Time, person: PersonID, scheduler: &mut Scheduler) {
+        self.leave_bus_stop(now, person, scheduler);
+        self.leave_bus(now, person, scheduler);
+    }
+
+    // Aborted trips can make somebody leave the map while they're already in a remote building, so
+    // leave that first.
+    fn enter_remote_bldg(
+        &mut self,
+        now: Time,
+        person: PersonID,
+        loc: OffMapLocation,
+        scheduler: &mut Scheduler,
+    ) {
+        self.leave_remote_bldg(now, person, scheduler);
+        if self.is_infectious(person) {
+            self.remote_bldgs.infectious_present(now, loc.clone());
+        }
+        self.remote_bldgs
+            .person_enters_space(now, person, loc.clone());
+        self.person_to_remote.insert(person, loc);
+    }
+
+    fn leave_remote_bldg(&mut self, now: Time, person: PersonID, scheduler: &mut Scheduler) {
+        if let Some(loc) = self.person_to_remote.remove(&person) {
+            if self.is_infectious(person) {
+                self.remote_bldgs.infectious_present(now, loc.clone());
+            }
+            let others = self
+                .remote_bldgs
+                .person_leaves_space(now, person, loc.clone())
+                .unwrap();
+            self.transmission(
+                now,
+                person,
+                others,
+                ContactSpace::RemoteBuilding(loc.parcel_id),
+                scheduler,
+            );
+        }
+    }
+
+    fn leave_bus_stop(&mut self, now: Time, person: PersonID, scheduler: &mut Scheduler) {
+        if let Some(stop) = self.person_to_stop.remove(&person) {
+            if self.is_infectious(person) {
+                self.bus_stops.infectious_present(now, stop);
+            }
+            let others = self
+                .bus_stops
+                .person_leaves_space(now, person, stop)
+                .unwrap();
+            self.transmission(now, person, others, ContactSpace::BusStop(stop), scheduler);
+        }
+    }
+
+    // If this person is on a bus, the ride's over, however it ended.
+    fn leave_bus(&mut self, now: Time, person: PersonID, scheduler: &mut Scheduler) {
+        if let Some(car) = self.person_to_bus.remove(&person) {
+            if self.is_infectious(person) {
+                self.buses.infectious_present(now, car);
+            }
+            let others = self.buses.person_leaves_space(now, person, car).unwrap();
+            self.transmission(now, person, others, ContactSpace::Bus(car), scheduler);
+        }
+    }
+
+    // How many people are inside each building right now? Empty buildings are omitted. Crowded
+    // buildings are where most transmission happens.
+    pub fn occupancy_by_building(&self) -> BTreeMap<BuildingID, usize> {
+        self.bldgs
+            .occupants
+            .keys()
+            .map(|b| (*b, self.bldgs.current_occupancy(b)))
+            .collect()
+    }
+
+    // Models closing schools, offices, etc. People inside these buildings still count as
+    // occupants, but nobody is exposed there before the reopening time. Closing a building again
+    // replaces the old reopening time.
+    pub fn close_buildings(&mut self, bldgs: BTreeSet<BuildingID>, until: Time) {
+        for b in bldgs {
+            self.closed_bldgs.insert(b, until);
+        }
+    }
+
+    pub fn handle_cmd(&mut self, now: Time, cmd: Cmd, scheduler: &mut Scheduler) {
         assert!(self.initialized);
 
         // TODO Here we might enforce policies. Like severe -> become hospitalized
@@ -238,8 +907,27 @@ impl PandemicModel {
             Cmd::BecomeHospitalized(_person) => {
                 // self.hospitalized.insert(person);
             }
-            Cmd::BecomeQuarantined(_person) => {
-                // self.quarantined.insert(person);
+            Cmd::BecomeQuarantined(person) => {
+                self.quarantined.insert(person);
+            }
+            Cmd::ImportCases(n) => {
+                let sane: Vec<PersonID> = self
+                    .pop
+                    .iter()
+                    .filter(|(_, state)| state.is_sane())
+                    .map(|(p, _)| p)
+                    .collect();
+                let imported: Vec<PersonID> = sane
+                    .choose_multiple(&mut self.intervention_rng, n)
+                    .cloned()
+                    .collect();
+                for person in imported {
+                    self.become_exposed_offmap(now, person);
+                }
+                scheduler.push(
+                    now + Duration::hours(24),
+                    Command::Pandemic(Cmd::ImportCases(n)),
+                );
             }
         }
     }
@@ -251,6 +939,12 @@ impl PandemicModel {
         }
     }
 
+    // How long has this person been in their current state? None for sane people, who've never
+    // entered any state.
+    pub fn time_in_current_state(&self, person: PersonID, now: Time) -> Option<Duration> {
+        self.get_time(person).map(|t| now - t)
+    }
+
     pub fn is_sane(&self, person: PersonID) -> bool {
         match self.pop.get(&person) {
             Some(state) => state.is_sane(),
@@ -265,6 +959,18 @@ impl PandemicModel {
         }
     }
 
+    // Hospitalized people are also infectious.
+    pub fn is_hospitalized(&self, person: PersonID) -> bool {
+        match self.pop.get(&person) {
+            Some(state) => state.is_hospitalized(),
+            None => unreachable!(),
+        }
+    }
+
+    pub fn is_quarantined(&self, person: PersonID) -> bool {
+        self.quarantined.contains(&person)
+    }
+
     pub fn is_exposed(&self, person: PersonID) -> bool {
         match self.pop.get(&person) {
             Some(state) => state.is_exposed(),
@@ -272,6 +978,14 @@ impl PandemicModel {
         }
     }
 
+    // No longer infectious, but not recovered yet.
+    pub fn is_convalescent(&self, person: PersonID) -> bool {
+        match self.pop.get(&person) {
+            Some(state) => state.is_convalescent(),
+            None => unreachable!(),
+        }
+    }
+
     pub fn is_recovered(&self, person: PersonID) -> bool {
         match self.pop.get(&person) {
             Some(state) => state.is_recovered(),
@@ -299,14 +1013,38 @@ impl PandemicModel {
         &mut self,
         now: Time,
         person: PersonID,
-        other_occupants: Vec<(PersonID, Duration)>,
+        mut other_occupants: Vec<(PersonID, Duration)>,
+        space: ContactSpace,
         scheduler: &mut Scheduler,
     ) {
+        // Each exposure consumes RNG, so the order matters. Don't depend on whatever order people
+        // happened to enter the space in.
+        other_occupants.sort_by_key(|(p, _)| *p);
+
         // person has spent some duration in the same space as other people. Does transmission
         // occur?
         for (other, overlap) in other_occupants {
+            let bin = (overlap.inner_seconds() / 300.0).floor() as u32 * 5;
+            *self.contact_durations.entry(bin).or_insert(0) += 1;
+
             if let Some(pid) = self.infectious_contact(person, other) {
+                let source = if pid == person { other } else { person };
                 self.become_exposed(now, overlap, pid, scheduler);
+                let exposed = self.is_exposed(pid);
+                if exposed {
+                    self.infected_by.insert(pid, source);
+                }
+                if self.config.trace_contacts {
+                    self.contact_trace.push(ContactTrace {
+                        time: now,
+                        sane: pid,
+                        infectious: source,
+                        space: space.clone(),
+                        overlap,
+                        probability: State::exposure_probability(overlap),
+                        exposed,
+                    });
+                }
             }
         }
     }
@@ -314,7 +1052,11 @@ impl PandemicModel {
     // transition from a state to another without interaction with others
     fn transition(&mut self, now: Time, person: PersonID, _scheduler: &mut Scheduler) {
         let state = self.pop.remove(&person).unwrap();
-        let state = state.next(AnyTime::from(now), &mut self.rng).unwrap();
+        let prev = state.entered_by();
+        let state = state
+            .next(AnyTime::from(now), &self.config, &mut self.progression_rng)
+            .unwrap();
+        self.record_history(person, prev, &state);
         self.pop.insert(person, state);
 
         // if self.rng.gen_bool(0.1) {
@@ -334,13 +1076,17 @@ impl PandemicModel {
     ) {
         // When poeple become expose
         let state = self.pop.remove(&person).unwrap();
-        assert_eq!(
-            state.get_event_time().unwrap().inner_seconds(),
-            std::f64::INFINITY
-        );
+        assert!(state.get_event_time().unwrap().is_never());
         let state = state
-            .start(AnyTime::from(now), overlap, &mut self.rng)
+            .start(
+                AnyTime::from(now),
+                overlap,
+                &self.config,
+                &mut self.transmission_rng,
+                &mut self.progression_rng,
+            )
             .unwrap();
+        self.record_history(person, None, &state);
         self.pop.insert(person, state);
 
         // if self.rng.gen_bool(0.1) {
@@ -350,6 +1096,58 @@ impl PandemicModel {
         //     );
         // }
     }
+
+    // For people exposed outside the map, like imported cases. Nothing about this depends on
+    // contacts, so don't draw from the transmission stream.
+    fn become_exposed_offmap(&mut self, now: Time, person: PersonID) {
+        let state = self.pop.remove(&person).unwrap();
+        let state = state
+            .start(
+                AnyTime::from(now),
+                Duration::seconds(std::f64::MAX),
+                &self.config,
+                &mut self.intervention_rng,
+                &mut self.progression_rng,
+            )
+            .unwrap();
+        self.record_history(person, None, &state);
+        self.pop.insert(person, state);
+    }
+}
+
+// The state of everybody, indexed directly by PersonID, since these are dense. This is looked up
+// for every person entering or leaving any shared space, so avoid a BTreeMap.
+#[derive(Clone)]
+struct Population {
+    states: Vec<Option<State>>,
+}
+
+impl Population {
+    fn new() -> Population {
+        Population { states: Vec::new() }
+    }
+
+    fn get(&self, person: &PersonID) -> Option<&State> {
+        self.states.get(person.0).and_then(|state| state.as_ref())
+    }
+
+    fn insert(&mut self, person: PersonID, state: State) {
+        if person.0 >= self.states.len() {
+            self.states.resize(person.0 + 1, None);
+        }
+        self.states[person.0] = Some(state);
+    }
+
+    fn remove(&mut self, person: &PersonID) -> Option<State> {
+        self.states.get_mut(person.0).and_then(|state| state.take())
+    }
+
+    fn iter(&self) -> impl Iterator<Item = (PersonID, &State)> {
+        self.states
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, state)| state.as_ref().map(|state| (PersonID(idx), state)))
+    }
 }
 
 #[derive(Clone)]
@@ -359,15 +1157,26 @@ struct SharedSpace<T: Ord> {
     // would require knowing the time. Want something closer to
     // https://guava.dev/releases/19.0/api/docs/com/google/common/collect/Table.html.
     occupants: BTreeMap<T, Vec<(PersonID, Time)>>,
+    // When was somebody infectious last inside each space? Only updated when they enter or leave.
+    last_infectious: BTreeMap<T, Time>,
 }
 
 impl<T: Ord> SharedSpace<T> {
     fn new() -> SharedSpace<T> {
         SharedSpace {
             occupants: BTreeMap::new(),
+            last_infectious: BTreeMap::new(),
         }
     }
 
+    fn infectious_present(&mut self, now: Time, space: T) {
+        self.last_infectious.insert(space, now);
+    }
+
+    fn last_infectious_present(&self, space: &T) -> Option<Time> {
+        self.last_infectious.get(space).cloned()
+    }
+
     fn person_enters_space(&mut self, now: Time, person: PersonID, space: T) {
         self.occupants
             .entry(space)
@@ -375,6 +1184,11 @@ impl<T: Ord> SharedSpace<T> {
             .push((person, now));
     }
 
+    // How many people are inside right now?
+    fn current_occupancy(&self, space: &T) -> usize {
+        self.occupants.get(space).map(|v| v.len()).unwrap_or(0)
+    }
+
     // Returns a list of all other people that the person was in the shared space with, and how
     // long their time overlapped. If it returns None, then a bug must have occurred, because
     // somebody has left a space they never entered.
@@ -386,7 +1200,8 @@ impl<T: Ord> SharedSpace<T> {
     ) -> Option<Vec<(PersonID, Duration)>> {
         // TODO Messy to mutate state inside a retain closure
         let mut inside_since: Option<Time> = None;
-        let occupants = self.occupants.entry(space).or_insert_with(Vec::new);
+        // Empty spaces aren't kept around, so if it's missing, nobody was inside.
+        let occupants = self.occupants.get_mut(&space)?;
         occupants.retain(|(p, t)| {
             if *p == person {
                 inside_since = Some(*t);
@@ -398,23 +1213,113 @@ impl<T: Ord> SharedSpace<T> {
         // TODO Bug!
         let inside_since = inside_since?;
 
-        Some(
-            occupants
-                .iter()
-                .map(|(p, t)| (*p, now - (*t).max(inside_since)))
-                .collect(),
-        )
+        let overlaps = occupants
+            .iter()
+            .map(|(p, t)| (*p, now - (*t).max(inside_since)))
+            .collect();
+        if occupants.is_empty() {
+            self.occupants.remove(&space);
+        }
+        Some(overlaps)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::pandemic::TempFile;
+    use crate::{
+        DrivingGoal, PandemicSummary, TripEndpoint, TripID, TripLeg, TripManager, TripMode,
+        VehicleType,
+    };
+    use geom::{LonLat, Speed};
+    use map_model::{BusRouteID, IntersectionID, LaneID, Map};
 
     fn time(x: usize) -> Time {
         Time::START_OF_DAY + Duration::hours(x)
     }
 
+    // A model where everybody starts sane, skipping the random seeding in initialize.
+    fn sane_model(num_people: usize, p_hosp: f64, p_death: f64) -> PandemicModel {
+        sane_model_with_config(
+            num_people,
+            PandemicConfig::default().p_hosp(p_hosp).p_death(p_death),
+        )
+    }
+
+    fn sane_model_with_config(num_people: usize, config: PandemicConfig) -> PandemicModel {
+        let mut model = PandemicModel::new(config, XorShiftRng::from_seed([42; 16]));
+        for id in 0..num_people {
+            model.pop.insert(PersonID(id), State::new());
+        }
+        model.initialized = true;
+        model
+    }
+
+    fn population(num_people: usize) -> TripManager {
+        let mut trips = TripManager::new();
+        for id in 0..num_people {
+            trips.new_person(
+                PersonID(id),
+                None,
+                Speed::meters_per_second(1.0),
+                Vec::new(),
+            );
+        }
+        trips
+    }
+
+    // Exposure always happens with an effectively infinite overlap.
+    fn force_exposure(model: &mut PandemicModel, now: Time, person: PersonID) {
+        let mut scheduler = Scheduler::new();
+        model.become_exposed(
+            now,
+            Duration::seconds(std::f64::MAX),
+            person,
+            &mut scheduler,
+        );
+        assert!(model.is_exposed(person));
+    }
+
+    #[test]
+    fn test_line_list() {
+        let person = PersonID(0);
+        // p_death is (confusingly) the probability of recovering directly from being infectious
+        let mut model = sane_model(2, 0.5, 1.0);
+        let mut scheduler = Scheduler::new();
+
+        force_exposure(&mut model, time(1), person);
+        // Transitions happen once the scheduled event time has passed; a day is plenty.
+        model.transition(time(30), person, &mut scheduler);
+        assert!(model.is_infectious(person));
+        model.transition(time(60), person, &mut scheduler);
+        assert!(model.is_recovered(person));
+
+        assert_eq!(
+            model.history[&person],
+            vec![
+                (StateEvent::Exposition, time(1)),
+                (StateEvent::Incubation, time(30)),
+                (StateEvent::Recovery, time(60)),
+            ]
+        );
+        assert_eq!(
+            model.line_list(),
+            vec![PersonRecord {
+                person,
+                exposed: Some(time(1)),
+                infectious: Some(time(30)),
+                cleared: None,
+                hospitalized: None,
+                recovered: Some(time(60)),
+                dead: None,
+            }]
+        );
+        assert_eq!(model.count_infected_at(time(29)), 0);
+        assert_eq!(model.count_infected_at(time(45)), 1);
+        assert_eq!(model.count_infected_at(time(60)), 0);
+    }
+
     #[test]
     fn test_overlap() {
         let mut space = SharedSpace::new();
@@ -469,4 +1374,1079 @@ mod tests {
             Some(vec![(person3, Duration::hours(5))])
         );
     }
+
+    #[test]
+    fn test_transmission_order() {
+        let infectious = PersonID(0);
+        let others: Vec<(PersonID, Duration)> = (1..20)
+            .map(|id| (PersonID(id), Duration::minutes(10 * id)))
+            .collect();
+        let mut reversed = others.clone();
+        reversed.reverse();
+
+        let mut results = Vec::new();
+        for occupants in vec![others, reversed] {
+            let mut model = sane_model(20, 0.5, 0.5);
+            let mut scheduler = Scheduler::new();
+            force_exposure(&mut model, time(0), infectious);
+            model.transition(time(30), infectious, &mut scheduler);
+            assert!(model.is_infectious(infectious));
+
+            model.transmission(
+                time(31),
+                infectious,
+                occupants,
+                ContactSpace::Building(BuildingID(0)),
+                &mut scheduler,
+            );
+            results.push(model.line_list());
+        }
+        assert_eq!(results[0], results[1]);
+    }
+
+    #[test]
+    fn test_attack_rate_by_building() {
+        let mut model = sane_model(4, 0.5, 0.5);
+        let bldg1 = BuildingID(1);
+        let bldg2 = BuildingID(2);
+        model.home.insert(PersonID(0), bldg1);
+        model.home.insert(PersonID(1), bldg1);
+        model.home.insert(PersonID(2), bldg2);
+        model.home.insert(PersonID(3), bldg2);
+
+        force_exposure(&mut model, time(1), PersonID(0));
+        force_exposure(&mut model, time(1), PersonID(1));
+
+        let rates = model.attack_rate_by_building();
+        assert_eq!(rates.len(), 2);
+        assert_eq!(rates[&bldg1], 1.0);
+        assert_eq!(rates[&bldg2], 0.0);
+    }
+
+    #[test]
+    fn test_any_time() {
+        let never = AnyTime::never();
+        let early = AnyTime::from(time(1));
+        let late = AnyTime::from(time(5));
+
+        assert!(never.is_never());
+        assert!(!never.is_finite());
+        assert!(!early.is_never());
+        assert!(early < late && late < never);
+
+        assert_eq!(early.min(late), early);
+        assert_eq!(early.max(late), late);
+        assert_eq!(late.max(never), never);
+
+        assert_eq!(AnyTime::from(time(0)).clamp(early, late), early);
+        assert_eq!(
+            AnyTime::from(time(3)).clamp(early, late),
+            AnyTime::from(time(3))
+        );
+        assert_eq!(never.clamp(early, late), late);
+    }
+
+    #[test]
+    fn test_any_time_into_time() {
+        assert_eq!(AnyTime::from(time(2)).try_into_time(), Ok(time(2)));
+        assert_eq!(
+            AnyTime::from(Time::START_OF_DAY).try_into_time(),
+            Ok(Time::START_OF_DAY)
+        );
+        assert!(AnyTime::from(-1.0).try_into_time().is_err());
+        assert!((AnyTime::from(time(1)) - Duration::hours(2))
+            .try_into_time()
+            .is_err());
+        assert!(AnyTime::never().try_into_time().is_err());
+    }
+
+    #[test]
+    fn test_config_builder() {
+        let config = PandemicConfig::default()
+            .p_hosp(0.1)
+            .p_death(0.2)
+            .initial_exposed_ratio(1.0)
+            .initial_infectious_ratio(1.0);
+        assert_eq!(config.p_hosp, 0.1);
+        assert_eq!(config.p_death, 0.2);
+
+        let trips = population(10);
+        let mut model = PandemicModel::new(config, XorShiftRng::from_seed([42; 16]));
+        model.initialize(trips.get_all_people(), &mut Scheduler::new());
+        assert_eq!(model.count_infected(), 10);
+        assert_eq!(model.count_sane(), 0);
+    }
+
+    #[test]
+    fn test_import_cases() {
+        let trips = population(100);
+        let config = PandemicConfig::default()
+            .initial_exposed_ratio(0.0)
+            .import_per_day(2);
+        let mut model = PandemicModel::new(config, XorShiftRng::from_seed([42; 16]));
+        let mut scheduler = Scheduler::new();
+        model.initialize(trips.get_all_people(), &mut scheduler);
+        assert_eq!(model.count_exposed(), 0);
+
+        for day in 1..=3 {
+            let now = time(24 * day);
+            assert_eq!(scheduler.peek_next_time(), Some(now));
+            match scheduler.get_next() {
+                Some(Command::Pandemic(cmd)) => model.handle_cmd(now, cmd, &mut scheduler),
+                x => panic!("Expected a pandemic command, got {:?}", x),
+            }
+            assert_eq!(model.count_exposed(), 2 * day);
+        }
+    }
+
+    #[test]
+    fn test_risk_interpolation() {
+        let config = PandemicConfig::default()
+            .p_hosp(0.0)
+            .p_death(1.0)
+            .risk_at(time(10), 1.0, 0.0)
+            .risk_at(time(20), 0.5, 0.5);
+        assert_eq!(config.risk(time(0)), (0.0, 1.0));
+        assert_eq!(config.risk(time(5)), (0.5, 0.5));
+        assert_eq!(config.risk(time(10)), (1.0, 0.0));
+        assert_eq!(config.risk(time(15)), (0.75, 0.25));
+        assert_eq!(config.risk(time(100)), (0.5, 0.5));
+        assert_eq!(PandemicConfig::default().risk(time(100)), (0.5, 0.5));
+    }
+
+    #[test]
+    fn test_risk_changes_over_time() {
+        // Confusingly, p_death is the probability of recovering straight from being infectious,
+        // and p_hosp the probability of recovering from the hospital. With both at 0, everybody
+        // dies.
+        let deadly = PandemicConfig::default().p_hosp(0.0).p_death(0.0);
+        // But treatment gets better after the first day.
+        let improving = deadly.clone().risk_at(time(24), 0.0, 1.0);
+
+        let mut deaths = Vec::new();
+        for config in vec![deadly, improving] {
+            let mut model = sane_model_with_config(50, config);
+            let mut scheduler = Scheduler::new();
+            for id in 0..50 {
+                let person = PersonID(id);
+                force_exposure(&mut model, time(100), person);
+                for t in &[130, 160, 190] {
+                    model.transition(time(*t), person, &mut scheduler);
+                }
+            }
+            deaths.push(model.count_dead());
+        }
+        assert_eq!(deaths, vec![50, 0]);
+    }
+
+    #[test]
+    fn test_is_hospitalized() {
+        let person = PersonID(0);
+        // Nobody recovers straight from being infectious, and everybody in the hospital dies.
+        let mut model = sane_model(1, 0.0, 0.0);
+        let mut scheduler = Scheduler::new();
+
+        force_exposure(&mut model, time(1), person);
+        assert!(!model.is_hospitalized(person));
+        model.transition(time(30), person, &mut scheduler);
+        assert!(model.is_infectious(person));
+        assert!(!model.is_hospitalized(person));
+        model.transition(time(60), person, &mut scheduler);
+        assert!(model.is_hospitalized(person));
+        // Still counts
+        assert!(model.is_infectious(person));
+        model.transition(time(90), person, &mut scheduler);
+        assert!(model.is_dead(person));
+        assert!(!model.is_hospitalized(person));
+    }
+
+    #[test]
+    fn test_is_quarantined() {
+        let mut model = sane_model(2, 0.5, 0.5);
+        let mut scheduler = Scheduler::new();
+        assert!(!model.is_quarantined(PersonID(0)));
+        model.handle_cmd(time(1), Cmd::BecomeQuarantined(PersonID(0)), &mut scheduler);
+        assert!(model.is_quarantined(PersonID(0)));
+        assert!(!model.is_quarantined(PersonID(1)));
+    }
+
+    #[test]
+    fn test_population() {
+        let mut pop = Population::new();
+        assert!(pop.get(&PersonID(3)).is_none());
+        // IDs don't have to be contiguous
+        pop.insert(PersonID(3), State::new());
+        pop.insert(PersonID(1), State::new());
+        assert!(pop.get(&PersonID(0)).is_none());
+        assert!(pop.get(&PersonID(1)).unwrap().is_sane());
+        assert_eq!(
+            pop.iter().map(|(p, _)| p).collect::<Vec<_>>(),
+            vec![PersonID(1), PersonID(3)]
+        );
+
+        assert!(pop.remove(&PersonID(1)).is_some());
+        assert!(pop.remove(&PersonID(1)).is_none());
+        assert!(pop.remove(&PersonID(10)).is_none());
+        assert_eq!(
+            pop.iter().map(|(p, _)| p).collect::<Vec<_>>(),
+            vec![PersonID(3)]
+        );
+    }
+
+    #[test]
+    fn test_population_matches_btreemap() {
+        // The BTreeMap this replaced is the reference. Do lots of random changes to a city-sized
+        // population and make sure every lookup and the final iteration order agree.
+        let mut rng = XorShiftRng::from_seed([42; 16]);
+        let mut pop = Population::new();
+        let mut expected: BTreeMap<PersonID, Time> = BTreeMap::new();
+        for step in 0..100_000 {
+            let person = PersonID(rng.gen_range(0, 20_000));
+            match rng.gen_range(0, 3) {
+                0 => {
+                    let t = Time::START_OF_DAY + Duration::seconds(step as f64);
+                    pop.insert(person, State::Recovered(t));
+                    expected.insert(person, t);
+                }
+                1 => {
+                    assert_eq!(
+                        pop.remove(&person).and_then(|s| s.get_time()),
+                        expected.remove(&person)
+                    );
+                }
+                _ => {
+                    assert_eq!(
+                        pop.get(&person).and_then(|s| s.get_time()),
+                        expected.get(&person).cloned()
+                    );
+                }
+            }
+        }
+        assert_eq!(
+            pop.iter()
+                .map(|(p, s)| (p, s.get_time().unwrap()))
+                .collect::<Vec<_>>(),
+            expected.into_iter().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_empty_spaces_removed() {
+        let mut space = SharedSpace::new();
+        let bldg = BuildingID(1);
+
+        space.person_enters_space(time(0), PersonID(1), bldg);
+        space.person_enters_space(time(1), PersonID(2), bldg);
+        assert_eq!(
+            space.person_leaves_space(time(2), PersonID(1), bldg),
+            Some(vec![(PersonID(2), Duration::hours(1))])
+        );
+        assert!(space.occupants.contains_key(&bldg));
+        assert_eq!(
+            space.person_leaves_space(time(3), PersonID(2), bldg),
+            Some(Vec::new())
+        );
+        assert!(space.occupants.is_empty());
+
+        // Leaving a space nobody's in doesn't create an entry
+        assert_eq!(space.person_leaves_space(time(4), PersonID(3), bldg), None);
+        assert!(space.occupants.is_empty());
+    }
+
+    #[test]
+    fn test_infection_tree() {
+        let mut model = sane_model(4, 0.5, 0.5);
+        let mut scheduler = Scheduler::new();
+        let (a, b, c) = (PersonID(0), PersonID(1), PersonID(2));
+        let forever = Duration::seconds(std::f64::MAX);
+
+        force_exposure(&mut model, time(0), a);
+        model.transition(time(30), a, &mut scheduler);
+        let space = ContactSpace::Building(BuildingID(0));
+        model.transmission(
+            time(31),
+            a,
+            vec![(b, forever)],
+            space.clone(),
+            &mut scheduler,
+        );
+        model.transition(time(62), b, &mut scheduler);
+        assert!(model.is_infectious(b));
+        // The infectious person can be either side of the contact
+        model.transmission(time(63), c, vec![(b, forever)], space, &mut scheduler);
+
+        assert_eq!(
+            model.infection_tree(),
+            vec![
+                (None, a, time(0)),
+                (Some(a), b, time(31)),
+                (Some(b), c, time(63)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_cmd_round_trip() {
+        // Commands live in the scheduler, so they're part of savestates. Add new variants here.
+        let cmds = vec![
+            Cmd::BecomeHospitalized(PersonID(3)),
+            Cmd::BecomeQuarantined(PersonID(5)),
+            Cmd::ImportCases(10),
+        ];
+        let file = TempFile::new("test_cmd_round_trip.bin");
+        let path = file.path.clone();
+        for cmd in cmds {
+            // No wildcard, so a new variant won't compile until it's added to the list above
+            match cmd {
+                Cmd::BecomeHospitalized(_) | Cmd::BecomeQuarantined(_) | Cmd::ImportCases(_) => {}
+            }
+            abstutil::write_binary(path.clone(), &cmd);
+            let copy: Cmd = abstutil::read_binary(path.clone(), &mut abstutil::Timer::throwaway());
+            assert_eq!(copy, cmd);
+        }
+    }
+
+    #[test]
+    fn test_presymptomatic() {
+        let person = PersonID(0);
+        let config = PandemicConfig::default()
+            .p_hosp(0.5)
+            .p_death(1.0)
+            .presymptomatic_period(Duration::minutes(30));
+        let mut model = sane_model_with_config(1, config);
+        let mut scheduler = Scheduler::new();
+
+        force_exposure(&mut model, time(1), person);
+        assert!(!model.is_infectious(person));
+        model.transition(time(5), person, &mut scheduler);
+        assert!(model.is_infectious(person));
+        assert_eq!(model.count_infected(), 1);
+        assert_eq!(model.count_exposed(), 0);
+
+        // Symptoms start after the presymptomatic period. Until then, nothing changes.
+        model.transition(time(5) + Duration::minutes(10), person, &mut scheduler);
+        assert_eq!(
+            model.history[&person],
+            vec![
+                (StateEvent::Exposition, time(1)),
+                (StateEvent::Contagion, time(5)),
+            ]
+        );
+        model.transition(time(6), person, &mut scheduler);
+        assert!(model.is_infectious(person));
+        assert_eq!(
+            model.history[&person],
+            vec![
+                (StateEvent::Exposition, time(1)),
+                (StateEvent::Contagion, time(5)),
+                (StateEvent::Incubation, time(6)),
+            ]
+        );
+        // The line list shows when they became infectious, not when symptoms started
+        assert_eq!(model.line_list()[0].infectious, Some(time(5)));
+    }
+
+    #[test]
+    fn test_close_buildings() {
+        let mut model = sane_model(2, 0.5, 0.5);
+        let mut scheduler = Scheduler::new();
+        let (infectious, sane) = (PersonID(0), PersonID(1));
+        let bldg = BuildingID(1);
+
+        force_exposure(&mut model, time(0), infectious);
+        model.transition(time(30), infectious, &mut scheduler);
+        assert!(model.is_infectious(infectious));
+        let mut closed = BTreeSet::new();
+        closed.insert(bldg);
+        model.close_buildings(closed, time(40));
+
+        model.handle_event(
+            time(31),
+            &Event::PersonEntersBuilding(infectious, bldg),
+            &mut scheduler,
+        );
+        model.handle_event(
+            time(31),
+            &Event::PersonEntersBuilding(sane, bldg),
+            &mut scheduler,
+        );
+        model.handle_event(
+            time(35),
+            &Event::PersonLeavesBuilding(sane, bldg),
+            &mut scheduler,
+        );
+        assert!(model.is_sane(sane));
+
+        // After reopening, the same building is dangerous again
+        model.handle_event(
+            time(36),
+            &Event::PersonEntersBuilding(sane, bldg),
+            &mut scheduler,
+        );
+        model.handle_event(
+            time(50),
+            &Event::PersonLeavesBuilding(sane, bldg),
+            &mut scheduler,
+        );
+        assert!(model.is_exposed(sane));
+    }
+
+    #[test]
+    fn test_rng_streams() {
+        let mut scheduler = Scheduler::new();
+        let mut base = sane_model(10, 0.5, 0.5);
+        let mut with_imports = sane_model(10, 0.5, 0.5);
+        with_imports.handle_cmd(time(0), Cmd::ImportCases(3), &mut scheduler);
+        assert_eq!(with_imports.count_exposed(), 3);
+
+        // Picking the imported cases moved the intervention stream...
+        assert_ne!(
+            base.intervention_rng.gen::<u64>(),
+            with_imports.intervention_rng.gen::<u64>()
+        );
+        // ...but contacts would still draw exactly the same numbers
+        let base_draws: Vec<u64> = (0..10).map(|_| base.transmission_rng.gen()).collect();
+        let other_draws: Vec<u64> = (0..10)
+            .map(|_| with_imports.transmission_rng.gen())
+            .collect();
+        assert_eq!(base_draws, other_draws);
+    }
+
+    #[test]
+    fn test_summary_peak() {
+        let mut model = sane_model(4, 0.5, 1.0);
+        let mut scheduler = Scheduler::new();
+        let mut summary = PandemicSummary::new(time(0), &model);
+
+        for id in 0..3 {
+            force_exposure(&mut model, time(0), PersonID(id));
+        }
+        model.transition(time(30), PersonID(0), &mut scheduler);
+        summary.update_model(time(30), &model);
+        model.transition(time(31), PersonID(1), &mut scheduler);
+        model.transition(time(31), PersonID(2), &mut scheduler);
+        summary.update_model(time(31), &model);
+        // Everybody recovers directly, and the peak stays put
+        for id in 0..3 {
+            model.transition(time(60), PersonID(id), &mut scheduler);
+        }
+        summary.update_model(time(60), &model);
+
+        assert_eq!(summary.peak_infected, 3);
+        assert_eq!(summary.peak_time, time(31));
+        assert_eq!(summary.final_counts.recovered, 3);
+        assert_eq!(summary.final_counts.dead, 0);
+        assert_eq!(summary.cumulative_infections, 3);
+        assert_eq!(summary.attack_rate(), 0.75);
+    }
+
+    #[test]
+    fn test_occupancy() {
+        let mut model = sane_model(3, 0.5, 0.5);
+        let mut scheduler = Scheduler::new();
+        let bldg = BuildingID(1);
+        for id in 0..3 {
+            model.handle_event(
+                time(1),
+                &Event::PersonEntersBuilding(PersonID(id), bldg),
+                &mut scheduler,
+            );
+        }
+        assert_eq!(model.bldgs.current_occupancy(&bldg), 3);
+        assert_eq!(model.bldgs.current_occupancy(&BuildingID(2)), 0);
+
+        model.handle_event(
+            time(2),
+            &Event::PersonLeavesBuilding(PersonID(1), bldg),
+            &mut scheduler,
+        );
+        let mut expected = BTreeMap::new();
+        expected.insert(bldg, 2);
+        assert_eq!(model.occupancy_by_building(), expected);
+    }
+
+    #[test]
+    fn test_contact_trace() {
+        let config = PandemicConfig::default().trace_contacts();
+        let mut model = sane_model_with_config(3, config);
+        let mut scheduler = Scheduler::new();
+        let (infectious, sane, exposed) = (PersonID(0), PersonID(1), PersonID(2));
+        let forever = Duration::seconds(std::f64::MAX);
+
+        force_exposure(&mut model, time(0), infectious);
+        model.transition(time(30), infectious, &mut scheduler);
+        // Forced exposures don't count as contacts
+        force_exposure(&mut model, time(0), exposed);
+        assert!(model.contact_trace().is_empty());
+
+        // Contacts with people who can't be infected aren't interesting
+        model.transmission(
+            time(31),
+            infectious,
+            vec![(sane, forever), (exposed, forever)],
+            ContactSpace::Building(BuildingID(1)),
+            &mut scheduler,
+        );
+        assert_eq!(
+            model.contact_trace(),
+            &vec![ContactTrace {
+                time: time(31),
+                sane,
+                infectious,
+                space: ContactSpace::Building(BuildingID(1)),
+                overlap: forever,
+                probability: 1.0,
+                exposed: true,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_reset() {
+        let trips = population(100);
+        let config = PandemicConfig::default()
+            .initial_exposed_ratio(0.5)
+            .import_per_day(1);
+
+        let mut model = PandemicModel::new(config.clone(), XorShiftRng::from_seed([1; 16]));
+        model.initialize(trips.get_all_people(), &mut Scheduler::new());
+        for id in 0..100 {
+            model.transition(time(60), PersonID(id), &mut Scheduler::new());
+        }
+        model.reset(XorShiftRng::from_seed([2; 16]));
+        assert_eq!(model.count_total(), 0);
+        model.initialize(trips.get_all_people(), &mut Scheduler::new());
+
+        let mut fresh = PandemicModel::new(config, XorShiftRng::from_seed([2; 16]));
+        fresh.initialize(trips.get_all_people(), &mut Scheduler::new());
+        assert_eq!(model.count_sane(), fresh.count_sane());
+        assert_eq!(model.count_exposed(), fresh.count_exposed());
+        assert_eq!(model.count_infected(), fresh.count_infected());
+        assert_eq!(model.line_list(), fresh.line_list());
+    }
+
+    #[test]
+    fn test_empty_population() {
+        let trips = population(0);
+        let config = PandemicConfig::default()
+            .initial_exposed_ratio(1.0)
+            .import_per_day(1);
+        let mut model = PandemicModel::new(config, XorShiftRng::from_seed([42; 16]));
+        let mut scheduler = Scheduler::new();
+        model.initialize(trips.get_all_people(), &mut scheduler);
+        // No pointless imports every day
+        assert_eq!(scheduler.peek_next_time(), None);
+        assert_eq!(model.count_total(), 0);
+        assert_eq!(model.count_exposed() + model.count_infected(), 0);
+
+        let summary = PandemicSummary::new(time(0), &model);
+        assert_eq!(summary.attack_rate(), 0.0);
+    }
+
+    #[test]
+    fn test_time_in_current_state() {
+        let person = PersonID(0);
+        let mut model = sane_model(1, 0.5, 0.5);
+        assert_eq!(model.time_in_current_state(person, time(1)), None);
+
+        force_exposure(&mut model, time(1), person);
+        assert_eq!(
+            model.time_in_current_state(person, time(2)),
+            Some(Duration::hours(1))
+        );
+    }
+
+    #[test]
+    fn test_bus_ride_ends_without_walking() {
+        let config = PandemicConfig::default().trace_contacts();
+        let mut model = sane_model_with_config(2, config);
+        let mut scheduler = Scheduler::new();
+        let (infectious, sane) = (PersonID(0), PersonID(1));
+        force_exposure(&mut model, time(0), infectious);
+        model.transition(time(30), infectious, &mut scheduler);
+
+        let stop = BusStopID {
+            sidewalk: LaneID(0),
+            idx: 0,
+        };
+        let bus = CarID(0, VehicleType::Bus);
+        for person in &[infectious, sane] {
+            for phase in &[
+                TripPhaseType::WaitingForBus(BusRouteID(0), stop),
+                TripPhaseType::RidingBus(BusRouteID(0), stop, bus),
+            ] {
+                model.handle_event(
+                    time(31),
+                    &Event::TripPhaseStarting(TripID(person.0), *person, None, phase.clone()),
+                    &mut scheduler,
+                );
+            }
+        }
+
+        // The trip is aborted mid-ride, warping the rider to their destination
+        model.handle_event(
+            time(32),
+            &Event::PersonEntersBuilding(sane, BuildingID(1)),
+            &mut scheduler,
+        );
+        let trace = model.contact_trace();
+        assert_eq!(trace.len(), 1);
+        assert_eq!(trace[0].space, ContactSpace::Bus(bus));
+        assert_eq!(trace[0].overlap, Duration::hours(1));
+    }
+
+    #[test]
+    fn test_people_in_state() {
+        let trips = population(100);
+        let config = PandemicConfig::default().initial_exposed_ratio(0.5);
+        let mut model = PandemicModel::new(config, XorShiftRng::from_seed([42; 16]));
+        model.initialize(trips.get_all_people(), &mut Scheduler::new());
+
+        let exposed: Vec<PersonID> = model.exposed_people().collect();
+        assert!(!exposed.is_empty());
+        assert_eq!(exposed.len(), model.count_exposed());
+        for id in 0..100 {
+            let person = PersonID(id);
+            assert_eq!(exposed.contains(&person), model.is_exposed(person));
+        }
+        assert_eq!(model.dead_people().count(), 0);
+    }
+
+    #[test]
+    fn test_offmap_stay() {
+        let mut model = sane_model(2, 0.5, 0.5);
+        let mut scheduler = Scheduler::new();
+        let (infectious, sane) = (PersonID(0), PersonID(1));
+        force_exposure(&mut model, time(0), infectious);
+        model.transition(time(30), infectious, &mut scheduler);
+
+        let loc = OffMapLocation {
+            parcel_id: 1,
+            gps: LonLat::new(0.0, 0.0),
+        };
+        let border = IntersectionID(0);
+        model.handle_event(
+            time(31),
+            &Event::PersonLeavesMap(infectious, TripMode::Drive, border, Some(loc.clone())),
+            &mut scheduler,
+        );
+        model.handle_event(
+            time(32),
+            &Event::PersonLeavesMap(sane, TripMode::Drive, border, Some(loc.clone())),
+            &mut scheduler,
+        );
+        // Nobody's exposed until somebody comes back
+        assert!(model.is_sane(sane));
+        // 8 hours together is plenty
+        model.handle_event(
+            time(40),
+            &Event::PersonEntersMap(sane, TripMode::Drive, border, Some(loc)),
+            &mut scheduler,
+        );
+        assert!(model.is_exposed(sane));
+        assert_eq!(model.infected_by[&sane], infectious);
+    }
+
+    #[test]
+    fn test_last_infectious_present() {
+        let mut model = sane_model(2, 0.5, 0.5);
+        let mut scheduler = Scheduler::new();
+        let (infectious, sane) = (PersonID(0), PersonID(1));
+        force_exposure(&mut model, time(0), infectious);
+        model.transition(time(30), infectious, &mut scheduler);
+
+        let (bldg1, bldg2) = (BuildingID(1), BuildingID(2));
+        model.handle_event(
+            time(31),
+            &Event::PersonEntersBuilding(infectious, bldg1),
+            &mut scheduler,
+        );
+        model.handle_event(
+            time(31),
+            &Event::PersonEntersBuilding(sane, bldg2),
+            &mut scheduler,
+        );
+        assert_eq!(
+            model.last_infectious_present(&ContactSpace::Building(bldg1)),
+            Some(time(31))
+        );
+        assert_eq!(
+            model.last_infectious_present(&ContactSpace::Building(bldg2)),
+            None
+        );
+
+        model.handle_event(
+            time(33),
+            &Event::PersonLeavesBuilding(infectious, bldg1),
+            &mut scheduler,
+        );
+        assert_eq!(
+            model.last_infectious_present(&ContactSpace::Building(bldg1)),
+            Some(time(33))
+        );
+    }
+
+    #[test]
+    fn test_growth_rate() {
+        // Infections double every hour: 1 person infectious at hour 1, 2 at hour 2, 4 at hour 3...
+        let mut model = sane_model(16, 0.5, 0.5);
+        model.infected_over_time = (0..5).map(|i| (time(i + 1), 1 << i)).collect();
+        assert_eq!(model.count_infected_at(time(1)), 1);
+        assert_eq!(model.count_infected_at(time(5)), 16);
+
+        let rate = model
+            .current_growth_rate(Duration::hours(2), time(4))
+            .unwrap();
+        assert!((rate - 24.0 * std::f64::consts::LN_2).abs() < 1e-9);
+        let doubling = model.doubling_time(Duration::hours(2), time(4)).unwrap();
+        assert!((doubling.inner_seconds() - 3600.0).abs() < 1e-6);
+
+        // Not enough history
+        assert_eq!(model.current_growth_rate(Duration::hours(5), time(4)), None);
+    }
+
+    #[test]
+    fn test_config_file() {
+        let file = TempFile::new("test_config_file.json");
+        let path = file.path.clone();
+        let mut timer = abstutil::Timer::throwaway();
+
+        let config = PandemicConfig::default()
+            .p_hosp(0.3)
+            .risk_at(time(10), 0.1, 0.2)
+            .presymptomatic_period(Duration::hours(2));
+        abstutil::write_json(path.clone(), &config);
+        let copy: PandemicConfig = abstutil::read_json(path.clone(), &mut timer);
+        assert_eq!(copy, config);
+
+        // Only override some fields
+        std::fs::write(&path, r#"{"p_hosp": 0.3}"#).unwrap();
+        let copy: PandemicConfig = abstutil::read_json(path.clone(), &mut timer);
+        assert_eq!(copy, PandemicConfig::default().p_hosp(0.3));
+
+        // Typos are caught
+        std::fs::write(&path, r#"{"p_hospital": 0.3}"#).unwrap();
+        assert!(abstutil::maybe_read_json::<PandemicConfig>(path.clone(), &mut timer).is_err());
+    }
+
+    #[test]
+    fn test_summary_quiet_stretch() {
+        let mut model = sane_model(3, 0.5, 1.0);
+        let mut summary = PandemicSummary::new(time(0), &model);
+
+        force_exposure(&mut model, time(2), PersonID(0));
+        summary.update_model(time(2), &model);
+        force_exposure(&mut model, time(3), PersonID(1));
+        summary.update_model(time(3), &model);
+        // Then nothing happens for a day
+        for hour in 4..=27 {
+            summary.update_model(time(hour), &model);
+        }
+        assert_eq!(summary.last_new_infection, Some(time(3)));
+        assert_eq!(summary.longest_without_infections, Duration::hours(24));
+
+        // A new case starts a new stretch, but the longest one is remembered
+        force_exposure(&mut model, time(28), PersonID(2));
+        summary.update_model(time(28), &model);
+        summary.update_model(time(30), &model);
+        assert_eq!(summary.last_new_infection, Some(time(28)));
+        assert_eq!(summary.quiet_since, time(28));
+        assert_eq!(summary.longest_without_infections, Duration::hours(24));
+    }
+
+    #[test]
+    fn test_contact_duration_histogram() {
+        let mut model = sane_model(4, 0.5, 0.5);
+        let mut scheduler = Scheduler::new();
+        model.transmission(
+            time(1),
+            PersonID(0),
+            vec![
+                (PersonID(1), Duration::minutes(3)),
+                (PersonID(2), Duration::minutes(4)),
+                (PersonID(3), Duration::minutes(12)),
+            ],
+            ContactSpace::Building(BuildingID(1)),
+            &mut scheduler,
+        );
+        model.transmission(
+            time(2),
+            PersonID(1),
+            vec![(PersonID(2), Duration::minutes(5))],
+            ContactSpace::Building(BuildingID(1)),
+            &mut scheduler,
+        );
+        let mut expected = BTreeMap::new();
+        expected.insert(0, 2);
+        expected.insert(5, 1);
+        expected.insert(10, 1);
+        assert_eq!(model.contact_duration_histogram(), &expected);
+        // Nobody's infectious, so nothing happened
+        assert_eq!(model.count_sane(), 4);
+    }
+
+    #[test]
+    fn test_abandoned_bus_wait() {
+        let config = PandemicConfig::default().trace_contacts();
+        let mut model = sane_model_with_config(2, config);
+        let mut scheduler = Scheduler::new();
+        let (infectious, sane) = (PersonID(0), PersonID(1));
+        force_exposure(&mut model, time(0), infectious);
+        model.transition(time(30), infectious, &mut scheduler);
+
+        let stop = BusStopID {
+            sidewalk: LaneID(0),
+            idx: 0,
+        };
+        for person in &[infectious, sane] {
+            model.handle_event(
+                time(31),
+                &Event::TripPhaseStarting(
+                    TripID(person.0),
+                    *person,
+                    None,
+                    TripPhaseType::WaitingForBus(BusRouteID(0), stop),
+                ),
+                &mut scheduler,
+            );
+        }
+
+        // The bus never comes, and the trip is aborted
+        model.handle_event(
+            time(33),
+            &Event::PersonEntersBuilding(sane, BuildingID(1)),
+            &mut scheduler,
+        );
+        assert_eq!(model.bus_stops.current_occupancy(&stop), 1);
+        let trace = model.contact_trace();
+        assert_eq!(trace.len(), 1);
+        assert_eq!(trace[0].space, ContactSpace::BusStop(stop));
+        assert_eq!(trace[0].overlap, Duration::hours(2));
+    }
+
+    #[test]
+    fn test_replay_draws() {
+        let trips = population(100);
+        let config = PandemicConfig::default()
+            .initial_exposed_ratio(0.5)
+            .initial_infectious_ratio(0.5);
+        let run = |model: &mut PandemicModel| {
+            model.initialize(trips.get_all_people(), &mut Scheduler::new());
+            for round in 1..=3 {
+                for id in 0..100 {
+                    model.transition(time(round * 24), PersonID(id), &mut Scheduler::new());
+                }
+            }
+        };
+
+        let mut original = PandemicModel::new(config.clone(), XorShiftRng::from_seed([1; 16]));
+        assert!(original.recorded_draws().is_none());
+        original.record_draws();
+        run(&mut original);
+        let log = original.recorded_draws().unwrap();
+        assert!(!log.intervention.is_empty());
+        assert!(!log.progression.is_empty());
+
+        // A different seed, but every draw comes from the log
+        let mut replayed = PandemicModel::new(config, XorShiftRng::from_seed([2; 16]));
+        replayed.replay_draws(log);
+        run(&mut replayed);
+        assert_eq!(original.line_list(), replayed.line_list());
+        assert_eq!(original.count_exposed(), replayed.count_exposed());
+        assert_eq!(original.count_infected(), replayed.count_infected());
+    }
+
+    #[test]
+    fn test_convalescent() {
+        let person = PersonID(0);
+        // p_death is (confusingly) the probability of recovering directly from being infectious
+        let config = PandemicConfig::default()
+            .p_hosp(0.5)
+            .p_death(1.0)
+            .convalescent_period(Duration::minutes(30));
+        let mut model = sane_model_with_config(1, config);
+        let mut scheduler = Scheduler::new();
+
+        force_exposure(&mut model, time(1), person);
+        model.transition(time(30), person, &mut scheduler);
+        assert!(model.is_infectious(person));
+
+        // They stop transmitting, but are still sick
+        model.transition(time(40), person, &mut scheduler);
+        assert!(!model.is_infectious(person));
+        assert!(model.is_convalescent(person));
+        assert!(!model.is_recovered(person));
+        assert_eq!(model.count_infected(), 1);
+        assert_eq!(model.infectious_people().count(), 0);
+
+        // Recovery comes after the convalescent period. Until then, nothing changes.
+        model.transition(time(40) + Duration::minutes(10), person, &mut scheduler);
+        assert!(!model.is_recovered(person));
+        model.transition(time(41), person, &mut scheduler);
+        assert!(!model.is_convalescent(person));
+        assert!(model.is_recovered(person));
+        assert_eq!(
+            model.history[&person],
+            vec![
+                (StateEvent::Exposition, time(1)),
+                (StateEvent::Incubation, time(30)),
+                (StateEvent::Clearance, time(40)),
+                (StateEvent::Recovery, time(41)),
+            ]
+        );
+        let record = &model.line_list()[0];
+        assert_eq!(record.cleared, Some(time(40)));
+        assert_eq!(record.recovered, Some(time(41)));
+        // Still counted as infected while convalescent
+        assert_eq!(model.count_infected_at(time(40)), 1);
+    }
+
+    #[test]
+    fn test_return_from_other_parcel() {
+        let mut model = sane_model(2, 0.5, 0.5);
+        let mut scheduler = Scheduler::new();
+        let (infectious, sane) = (PersonID(0), PersonID(1));
+        force_exposure(&mut model, time(0), infectious);
+        model.transition(time(30), infectious, &mut scheduler);
+
+        let loc = |parcel_id| OffMapLocation {
+            parcel_id,
+            gps: LonLat::new(0.0, 0.0),
+        };
+        let border = IntersectionID(0);
+        // Leave to one parcel, and come back from another
+        model.handle_event(
+            time(31),
+            &Event::PersonLeavesMap(infectious, TripMode::Drive, border, Some(loc(1))),
+            &mut scheduler,
+        );
+        model.handle_event(
+            time(32),
+            &Event::PersonEntersMap(infectious, TripMode::Drive, border, Some(loc(2))),
+            &mut scheduler,
+        );
+        assert_eq!(model.remote_bldgs.current_occupancy(&loc(1)), 0);
+
+        // Nobody's left behind in the first parcel to expose later visitors
+        model.handle_event(
+            time(33),
+            &Event::PersonLeavesMap(sane, TripMode::Drive, border, Some(loc(1))),
+            &mut scheduler,
+        );
+        model.handle_event(
+            time(41),
+            &Event::PersonEntersMap(sane, TripMode::Drive, border, Some(loc(1))),
+            &mut scheduler,
+        );
+        assert!(model.is_sane(sane));
+
+        // Aborted trips can leave the map again from a remote building. Only the latest counts.
+        model.handle_event(
+            time(42),
+            &Event::PersonEntersRemoteBuilding(infectious, loc(3)),
+            &mut scheduler,
+        );
+        model.handle_event(
+            time(43),
+            &Event::PersonLeavesMap(infectious, TripMode::Drive, border, Some(loc(4))),
+            &mut scheduler,
+        );
+        assert_eq!(model.remote_bldgs.current_occupancy(&loc(3)), 0);
+        assert_eq!(model.remote_bldgs.current_occupancy(&loc(4)), 1);
+    }
+
+    #[test]
+    fn test_start_inside_building() {
+        let mut trips = population(2);
+        let (home, work) = (BuildingID(1), BuildingID(2));
+        for id in 0..2 {
+            trips.new_trip(
+                PersonID(id),
+                time(1),
+                TripEndpoint::Bldg(home),
+                TripMode::Drive,
+                vec![TripLeg::Drive(
+                    CarID(id, VehicleType::Car),
+                    DrivingGoal::ParkNear(work),
+                )],
+                &Map::blank(),
+            );
+        }
+
+        // The same order as Sim::flush_spawner. The first trip puts everybody in their starting
+        // building, and handling that before initialize would panic.
+        let config = PandemicConfig::default().initial_exposed_ratio(0.0);
+        let mut model = PandemicModel::new(config, XorShiftRng::from_seed([42; 16]));
+        let mut scheduler = Scheduler::new();
+        model.initialize(trips.get_all_people(), &mut scheduler);
+        for ev in trips.collect_events() {
+            model.handle_event(Time::START_OF_DAY, &ev, &mut scheduler);
+        }
+        assert_eq!(model.occupancy_by_building()[&home], 2);
+
+        // The first departure finds them inside, instead of panicking
+        model.handle_event(
+            time(1),
+            &Event::PersonLeavesBuilding(PersonID(0), home),
+            &mut scheduler,
+        );
+        assert_eq!(model.occupancy_by_building()[&home], 1);
+    }
+
+    #[test]
+    fn test_same_seed_same_summary() {
+        let trips = population(100);
+        let config = PandemicConfig::default()
+            .initial_exposed_ratio(0.5)
+            .initial_infectious_ratio(0.5);
+        let run = |seed: u128| {
+            let mut model =
+                PandemicModel::new(config.clone(), XorShiftRng::from_seed(seed.to_le_bytes()));
+            model.initialize(trips.get_all_people(), &mut Scheduler::new());
+            for round in 1..=3 {
+                for id in 0..100 {
+                    model.transition(time(round * 24), PersonID(id), &mut Scheduler::new());
+                }
+            }
+            (PandemicSummary::new(time(72), &model), model.line_list())
+        };
+
+        // Seeds that only differ in the last byte still give different runs
+        let seed = 0x0123_4567_89ab_cdef_fedc_ba98_7654_3210;
+        assert_eq!(run(seed), run(seed));
+        assert_ne!(run(seed).1, run(seed ^ (1 << 127)).1);
+    }
+
+    #[test]
+    fn test_count_all() {
+        let mut model = sane_model(5, 0.5, 1.0);
+        let mut scheduler = Scheduler::new();
+        for id in 0..3 {
+            force_exposure(&mut model, time(0), PersonID(id));
+        }
+        model.transition(time(30), PersonID(0), &mut scheduler);
+        model.transition(time(30), PersonID(1), &mut scheduler);
+        model.transition(time(60), PersonID(0), &mut scheduler);
+
+        // One pass gives the same answer as each count separately
+        let counts = model.count_all(time(60));
+        assert_eq!(counts.time, time(60));
+        assert_eq!(
+            (
+                counts.sane,
+                counts.exposed,
+                counts.infected,
+                counts.recovered,
+                counts.dead
+            ),
+            (2, 1, 1, 1, 0)
+        );
+        assert_eq!(counts.sane, model.count_sane());
+        assert_eq!(counts.exposed, model.count_exposed());
+        assert_eq!(counts.infected, model.count_infected());
+        assert_eq!(counts.recovered, model.count_recovered());
+        assert_eq!(counts.dead, model.count_dead());
+        assert_eq!(counts.total(), model.count_total());
+        assert_eq!(model.count_total(), 5);
+    }
 }
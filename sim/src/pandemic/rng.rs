@@ -0,0 +1,98 @@
+use rand::{Error, RngCore};
+use rand_xorshift::XorShiftRng;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+// One of the model's random streams. Normally this just passes draws through, but it can also log
+// every draw, and later feed a log back instead of sampling. A replayed run makes exactly the same
+// random choices as the recorded one, even if the code around the draws changed a bit.
+#[derive(Clone)]
+pub struct PandemicRng {
+    rng: XorShiftRng,
+    mode: Mode,
+}
+
+#[derive(Clone)]
+enum Mode {
+    Live,
+    Recording(Vec<u64>),
+    Replaying(VecDeque<u64>),
+}
+
+// Every draw from the model's random streams, from when recording started
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct DrawLog {
+    pub transmission: Vec<u64>,
+    pub progression: Vec<u64>,
+    pub intervention: Vec<u64>,
+}
+
+impl PandemicRng {
+    pub fn new(rng: XorShiftRng) -> PandemicRng {
+        PandemicRng {
+            rng,
+            mode: Mode::Live,
+        }
+    }
+
+    pub fn start_recording(&mut self) {
+        self.mode = Mode::Recording(Vec::new());
+    }
+
+    // None if this isn't recording
+    pub fn recorded(&self) -> Option<Vec<u64>> {
+        match self.mode {
+            Mode::Recording(ref log) => Some(log.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn replay(&mut self, log: Vec<u64>) {
+        self.mode = Mode::Replaying(log.into());
+    }
+
+    fn draw(&mut self, live: impl FnOnce(&mut XorShiftRng) -> u64) -> u64 {
+        match self.mode {
+            Mode::Live => live(&mut self.rng),
+            Mode::Recording(ref mut log) => {
+                let x = live(&mut self.rng);
+                log.push(x);
+                x
+            }
+            Mode::Replaying(ref mut log) => {
+                if let Some(x) = log.pop_front() {
+                    x
+                } else {
+                    panic!("Replaying a pandemic run needs more random draws than were recorded");
+                }
+            }
+        }
+    }
+}
+
+impl RngCore for PandemicRng {
+    // Without recording or replaying, every draw is exactly what the wrapped RNG would produce.
+    fn next_u32(&mut self) -> u32 {
+        self.draw(|rng| u64::from(rng.next_u32())) as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.draw(|rng| rng.next_u64())
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        if let Mode::Live = self.mode {
+            self.rng.fill_bytes(dest);
+            return;
+        }
+        for chunk in dest.chunks_mut(8) {
+            let bytes = self.next_u64().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
@@ -1,10 +1,11 @@
 use crate::{
     AgentID, AlertLocation, Analytics, CarID, Command, CreateCar, DrawCarInput, DrawPedCrowdInput,
     DrawPedestrianInput, DrivingSimState, Event, GetDrawAgents, IntersectionSimState, OrigPersonID,
-    PandemicModel, ParkedCar, ParkingSimState, ParkingSpot, PedestrianID, Person, PersonID,
-    PersonState, Router, Scheduler, SidewalkPOI, SidewalkSpot, TransitSimState, TripEndpoint,
-    TripID, TripManager, TripMode, TripPhaseType, TripPositions, TripResult, TripSpawner,
-    UnzoomedAgent, Vehicle, VehicleSpec, VehicleType, WalkingSimState, BUS_LENGTH, MIN_CAR_LENGTH,
+    PandemicConfig, PandemicModel, ParkedCar, ParkingSimState, ParkingSpot, PedestrianID, Person,
+    PersonID, PersonState, Router, Scheduler, SidewalkPOI, SidewalkSpot, TransitSimState,
+    TripEndpoint, TripID, TripManager, TripMode, TripPhaseType, TripPositions, TripResult,
+    TripSpawner, UnzoomedAgent, Vehicle, VehicleSpec, VehicleType, WalkingSimState, BUS_LENGTH,
+    MIN_CAR_LENGTH,
 };
 use abstutil::Timer;
 use derivative::Derivative;
@@ -74,7 +75,10 @@ pub struct SimOptions {
     pub dont_block_the_box: bool,
     pub recalc_lanechanging: bool,
     pub break_turn_conflict_cycles: bool,
+    // Pedestrians slow down going uphill, using the elevation of intersections
+    pub grade_affects_walking: bool,
     pub enable_pandemic_model: Option<XorShiftRng>,
+    pub pandemic_config: PandemicConfig,
     pub alerts: AlertHandler,
 }
 
@@ -103,7 +107,9 @@ impl SimOptions {
             dont_block_the_box: true,
             recalc_lanechanging: true,
             break_turn_conflict_cycles: true,
+            grade_affects_walking: false,
             enable_pandemic_model: None,
+            pandemic_config: PandemicConfig::default(),
             alerts: AlertHandler::Print,
         }
     }
@@ -119,7 +125,7 @@ impl Sim {
         Sim {
             driving: DrivingSimState::new(map, opts.recalc_lanechanging),
             parking: ParkingSimState::new(map, timer),
-            walking: WalkingSimState::new(),
+            walking: WalkingSimState::new(opts.grade_affects_walking),
             intersections: IntersectionSimState::new(
                 map,
                 &mut scheduler,
@@ -130,7 +136,7 @@ impl Sim {
             transit: TransitSimState::new(),
             trips: TripManager::new(),
             pandemic: if let Some(rng) = opts.enable_pandemic_model {
-                Some(PandemicModel::new(rng))
+                Some(PandemicModel::new(opts.pandemic_config, rng))
             } else {
                 None
             },
@@ -160,6 +166,9 @@ impl Sim {
             m.initialize(self.trips.get_all_people(), &mut self.scheduler);
         }
 
+        // Creating trips emits PersonEntersBuilding for everybody starting the day inside, so
+        // this has to happen after the pandemic model is initialized. Otherwise their first
+        // PersonLeavesBuilding wouldn't match anything.
         self.dispatch_events(Vec::new(), map);
     }
 
@@ -724,10 +733,28 @@ impl Sim {
         self.run_until_done(map, |_, _| {}, time_limit);
     }
 
-    pub fn run_until_done<F: Fn(&mut Sim, &Map)>(
+    pub fn run_until_done<F: FnMut(&mut Sim, &Map)>(
         &mut self,
         map: &Map,
-        callback: F,
+        mut callback: F,
+        // Interpreted as a relative time
+        time_limit: Option<Duration>,
+    ) {
+        self.run_until_done_or_stopped(
+            map,
+            |sim, map| {
+                callback(sim, map);
+                false
+            },
+            time_limit,
+        );
+    }
+
+    // Like run_until_done, but also stops as soon as the callback returns true.
+    pub fn run_until_done_or_stopped<F: FnMut(&mut Sim, &Map) -> bool>(
+        &mut self,
+        map: &Map,
+        mut callback: F,
         // Interpreted as a relative time
         time_limit: Option<Duration>,
     ) {
@@ -767,7 +794,10 @@ impl Sim {
                 last_print = Instant::now();
                 last_sim_time = self.time();
             }
-            callback(self, map);
+            if callback(self, map) {
+                println!("{}: stopped early", self.time());
+                break;
+            }
             if self.is_done() {
                 println!(
                     "{}: speed = {:.2}x, {}",